@@ -4,6 +4,9 @@ use rosetta_core::crypto::Algorithm;
 use rosetta_core::BlockchainConfig;
 use std::sync::Arc;
 
+/// Port bitcoind publishes new block hashes on.
+pub const ZMQ_PORT: u16 = 28332;
+
 pub fn config(network: &str) -> Result<BlockchainConfig> {
     anyhow::ensure!(network == "regtest");
     Ok(BlockchainConfig {
@@ -14,6 +17,7 @@ pub fn config(network: &str) -> Result<BlockchainConfig> {
         coin: 1,
         bip44: true,
         utxo: true,
+        historical_balance_lookup: false,
         currency_unit: "satoshi",
         currency_symbol: "tBTC",
         currency_decimals: 8,
@@ -27,9 +31,10 @@ pub fn config(network: &str) -> Result<BlockchainConfig> {
                 "-rpcallowip=0.0.0.0/0".into(),
                 "-rpcuser=rosetta".into(),
                 "-rpcpassword=rosetta".into(),
+                format!("-zmqpubhashblock=tcp://0.0.0.0:{ZMQ_PORT}"),
             ]
         }),
-        node_additional_ports: &[],
+        node_additional_ports: &[ZMQ_PORT],
         connector_port: 8080,
         testnet: network == "regtest",
     })
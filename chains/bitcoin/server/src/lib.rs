@@ -1,16 +1,34 @@
 use anyhow::Result;
+use bitcoincore_rpc_async::bitcoin::hashes::Hash;
+use bitcoincore_rpc_async::bitcoin::{self, BlockHash, Network, Txid};
 use bitcoincore_rpc_async::{Auth, Client, RpcApi};
+use futures::StreamExt;
+use rosetta_config_bitcoin::ZMQ_PORT;
 use rosetta_server::crypto::address::Address;
-use rosetta_server::crypto::PublicKey;
+use rosetta_server::crypto::{PublicKey, SecretKey, Signature};
 use rosetta_server::types::{
-    Block, BlockIdentifier, CallRequest, Coin, PartialBlockIdentifier, Transaction,
-    TransactionIdentifier,
+    AccountIdentifier, Amount, BalanceExemption, Block, BlockIdentifier, CallRequest, Coin,
+    CoinAction, CoinChange, CoinIdentifier, Operation, OperationIdentifier, OperationStatus,
+    PartialBlockIdentifier, Peer, Transaction, TransactionIdentifier,
 };
-use rosetta_server::{BlockchainClient, BlockchainConfig};
-use serde_json::Value;
+use rosetta_server::{
+    BlockStream, BlockchainClient, BlockchainConfig, ParsedTransaction, TransactionBuilder,
+    UnsignedTransaction,
+};
+use serde_json::{json, Value};
+use std::str::FromStr;
+use std::sync::Arc;
+
+mod zmq;
+
+const INPUT_OP_TYPE: &str = "INPUT";
+const OUTPUT_OP_TYPE: &str = "OUTPUT";
+/// Confirmations after which a block is considered final.
+const FINALITY_CONFIRMATIONS: u64 = 6;
 
 pub struct BitcoinClient {
     config: BlockchainConfig,
+    addr: String,
     client: Client,
     genesis_block: BlockIdentifier,
 }
@@ -19,9 +37,14 @@ pub struct BitcoinClient {
 impl BlockchainClient for BitcoinClient {
     type MetadataParams = ();
     type Metadata = ();
+    type TransactionBuilder = BitcoinTransactionBuilder;
+
+    fn create_config(network: &str) -> Result<BlockchainConfig> {
+        rosetta_config_bitcoin::config(network)
+    }
 
     async fn new(network: &str, addr: &str) -> Result<Self> {
-        let config = rosetta_config_bitcoin::config(network)?;
+        let config = Self::create_config(network)?;
         let client = Client::new(
             addr.to_string(),
             Auth::UserPass("rosetta".into(), "rosetta".into()),
@@ -34,6 +57,7 @@ impl BlockchainClient for BitcoinClient {
         };
         Ok(Self {
             config,
+            addr: addr.into(),
             client,
             genesis_block,
         })
@@ -47,6 +71,22 @@ impl BlockchainClient for BitcoinClient {
         &self.genesis_block
     }
 
+    fn operation_statuses(&self) -> Vec<OperationStatus> {
+        vec![]
+    }
+
+    fn operation_types(&self) -> Vec<String> {
+        vec![INPUT_OP_TYPE.into(), OUTPUT_OP_TYPE.into()]
+    }
+
+    fn call_methods(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn balance_exemptions(&self) -> Vec<BalanceExemption> {
+        vec![]
+    }
+
     async fn node_version(&self) -> Result<String> {
         let info = self.client.get_network_info().await?;
         let major = info.version / 10000;
@@ -65,6 +105,68 @@ impl BlockchainClient for BitcoinClient {
         })
     }
 
+    async fn finalized_block(&self) -> Result<BlockIdentifier> {
+        let index = self
+            .client
+            .get_block_count()
+            .await?
+            .saturating_sub(FINALITY_CONFIRMATIONS - 1);
+        let hash = self.client.get_block_hash(index).await?;
+        Ok(BlockIdentifier {
+            index,
+            hash: hex::encode(hash.as_ref()),
+        })
+    }
+
+    async fn block_identifier(
+        &self,
+        block_identifier: &PartialBlockIdentifier,
+    ) -> Result<BlockIdentifier> {
+        let hash = if let Some(hash) = block_identifier.hash.as_ref() {
+            BlockHash::from_slice(&hex::decode(hash)?)?
+        } else if let Some(index) = block_identifier.index {
+            self.client.get_block_hash(index).await?
+        } else {
+            self.client.get_best_block_hash().await?
+        };
+        let info = self.client.get_block_header_info(&hash).await?;
+        Ok(BlockIdentifier {
+            index: info.height as _,
+            hash: hex::encode(hash.as_ref()),
+        })
+    }
+
+    async fn block_timestamp(&self, block: &BlockIdentifier) -> Result<i64> {
+        let hash = BlockHash::from_slice(&hex::decode(&block.hash)?)?;
+        let info = self.client.get_block_header_info(&hash).await?;
+        Ok(info.time as i64 * 1000)
+    }
+
+    async fn peers(&self) -> Result<Vec<Peer>> {
+        Ok(self
+            .client
+            .get_peer_info()
+            .await?
+            .into_iter()
+            .map(|peer| Peer {
+                peer_id: peer.id.to_string(),
+                metadata: Some(json!({
+                    "addr": peer.addr,
+                    "inbound": peer.inbound,
+                    "subver": peer.subver,
+                })),
+            })
+            .collect())
+    }
+
+    async fn syncing(&self) -> Result<bool> {
+        Ok(self
+            .client
+            .get_blockchain_info()
+            .await?
+            .initial_block_download)
+    }
+
     async fn balance(&self, _address: &Address, _block: &BlockIdentifier) -> Result<u128> {
         todo!()
     }
@@ -101,9 +203,169 @@ impl BlockchainClient for BitcoinClient {
         anyhow::bail!("not implemented")
     }
 
+    async fn mempool(&self) -> Result<Vec<TransactionIdentifier>> {
+        Ok(self
+            .client
+            .get_raw_mempool()
+            .await?
+            .into_iter()
+            .map(|txid| TransactionIdentifier {
+                hash: txid.to_string(),
+            })
+            .collect())
+    }
+
+    async fn mempool_transaction(&self, tx: &TransactionIdentifier) -> Result<Transaction> {
+        let txid = Txid::from_str(&tx.hash)?;
+        let transaction = self.client.get_raw_transaction(&txid, None).await?;
+        let mut operations = vec![];
+        for input in &transaction.input {
+            if input.previous_output.is_null() {
+                continue;
+            }
+            operations.push(Operation {
+                operation_identifier: OperationIdentifier::new(operations.len() as _),
+                r#type: INPUT_OP_TYPE.into(),
+                coin_change: Some(CoinChange {
+                    coin_identifier: CoinIdentifier {
+                        identifier: input.previous_output.to_string(),
+                    },
+                    coin_action: CoinAction::Spent,
+                }),
+                ..Default::default()
+            });
+        }
+        for (vout, output) in transaction.output.iter().enumerate() {
+            // the connector only supports regtest
+            let account = bitcoin::Address::from_script(&output.script_pubkey, Network::Regtest)
+                .map(|address| AccountIdentifier::new(address.to_string()));
+            operations.push(Operation {
+                operation_identifier: OperationIdentifier::new(operations.len() as _),
+                r#type: OUTPUT_OP_TYPE.into(),
+                account,
+                amount: Some(Amount {
+                    value: output.value.to_string(),
+                    currency: self.config.currency(),
+                    metadata: None,
+                }),
+                coin_change: Some(CoinChange {
+                    coin_identifier: CoinIdentifier {
+                        identifier: format!("{txid}:{vout}"),
+                    },
+                    coin_action: CoinAction::Created,
+                }),
+                ..Default::default()
+            });
+        }
+        Ok(Transaction {
+            transaction_identifier: tx.clone(),
+            operations,
+            related_transactions: None,
+            metadata: None,
+        })
+    }
+
     async fn call(&self, _req: &CallRequest) -> Result<Value> {
         anyhow::bail!("not implemented")
     }
+
+    async fn listen(&self) -> Result<Option<BlockStream>> {
+        let host = self
+            .addr
+            .rsplit_once(':')
+            .map_or(&*self.addr, |(host, _)| host);
+        let hashes = zmq::subscribe(&format!("{host}:{ZMQ_PORT}"), "hashblock").await?;
+        let client = Arc::new(
+            Client::new(
+                self.addr.clone(),
+                Auth::UserPass("rosetta".into(), "rosetta".into()),
+            )
+            .await?,
+        );
+        let stream = hashes.filter_map(move |message| {
+            let client = client.clone();
+            async move {
+                // hashes are published in rpc byte order
+                let mut hash = message.get(1)?.clone();
+                hash.reverse();
+                let hash = BlockHash::from_slice(&hash).ok()?;
+                let info = client.get_block_header_info(&hash).await.ok()?;
+                Some(BlockIdentifier {
+                    index: info.height as _,
+                    hash: hex::encode(hash.as_ref()),
+                })
+            }
+        });
+        Ok(Some(stream.boxed()))
+    }
+}
+
+#[derive(Default)]
+pub struct BitcoinTransactionBuilder;
+
+impl TransactionBuilder for BitcoinTransactionBuilder {
+    type MetadataParams = ();
+    type Metadata = ();
+
+    fn transfer(&self, _address: &Address, _amount: u128) -> Result<Self::MetadataParams> {
+        anyhow::bail!("not implemented")
+    }
+
+    fn method_call(
+        &self,
+        _contract: &str,
+        _method: &str,
+        _values: &[String],
+        _amount: u128,
+    ) -> Result<Self::MetadataParams> {
+        anyhow::bail!("not implemented")
+    }
+
+    fn deploy_contract(&self, _contract_binary: Vec<u8>) -> Result<Self::MetadataParams> {
+        anyhow::bail!("not implemented")
+    }
+
+    fn create_unsigned(
+        &self,
+        _config: &BlockchainConfig,
+        _metadata_params: &Self::MetadataParams,
+        _metadata: &Self::Metadata,
+        _public_key: &PublicKey,
+    ) -> Result<UnsignedTransaction> {
+        anyhow::bail!("not implemented")
+    }
+
+    fn combine(
+        &self,
+        _config: &BlockchainConfig,
+        _transaction: &[u8],
+        _signature: &Signature,
+    ) -> Result<Vec<u8>> {
+        anyhow::bail!("not implemented")
+    }
+
+    fn create_and_sign(
+        &self,
+        _config: &BlockchainConfig,
+        _metadata_params: &Self::MetadataParams,
+        _metadata: &Self::Metadata,
+        _secret_key: &SecretKey,
+    ) -> Result<Vec<u8>> {
+        anyhow::bail!("not implemented")
+    }
+
+    fn parse(
+        &self,
+        _config: &BlockchainConfig,
+        _transaction: &[u8],
+        _signed: bool,
+    ) -> Result<ParsedTransaction> {
+        anyhow::bail!("not implemented")
+    }
+
+    fn hash(&self, _config: &BlockchainConfig, _transaction: &[u8]) -> Result<Vec<u8>> {
+        anyhow::bail!("not implemented")
+    }
 }
 
 #[cfg(test)]
@@ -135,6 +397,18 @@ mod tests {
         rosetta_server::tests::account(config).await
     }
 
+    #[tokio::test]
+    async fn test_construction_derive() -> Result<()> {
+        let config = rosetta_config_bitcoin::config("regtest")?;
+        rosetta_server::tests::construction_derive(config).await
+    }
+
+    #[tokio::test]
+    async fn test_mempool() -> Result<()> {
+        let config = rosetta_config_bitcoin::config("regtest")?;
+        rosetta_server::tests::mempool(config).await
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_construction() -> Result<()> {
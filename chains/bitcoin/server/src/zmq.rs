@@ -0,0 +1,141 @@
+//! Minimal ZMTP 3.0 subscriber for the notifications published by bitcoind.
+use anyhow::{ensure, Result};
+use futures::stream::{self, BoxStream, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const FLAG_MORE: u8 = 0x01;
+const FLAG_LONG: u8 = 0x02;
+const FLAG_COMMAND: u8 = 0x04;
+
+fn greeting() -> [u8; 64] {
+    let mut greeting = [0; 64];
+    // signature
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    // version 3.0, so the publisher accepts subscriptions sent as messages
+    greeting[10] = 3;
+    // NULL security mechanism, as-server is left unset
+    greeting[12..16].copy_from_slice(b"NULL");
+    greeting
+}
+
+fn frame(flags: u8, body: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(body.len() + 9);
+    if body.len() > u8::MAX as usize {
+        frame.push(flags | FLAG_LONG);
+        frame.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    } else {
+        frame.push(flags);
+        frame.push(body.len() as u8);
+    }
+    frame.extend_from_slice(body);
+    frame
+}
+
+fn ready() -> Vec<u8> {
+    let mut body = vec![5];
+    body.extend_from_slice(b"READY");
+    body.push(11);
+    body.extend_from_slice(b"Socket-Type");
+    body.extend_from_slice(&3u32.to_be_bytes());
+    body.extend_from_slice(b"SUB");
+    frame(FLAG_COMMAND, &body)
+}
+
+/// Reads a frame and returns its flags and body.
+async fn read_frame(socket: &mut TcpStream) -> Result<(u8, Vec<u8>)> {
+    let flags = socket.read_u8().await?;
+    let len = if flags & FLAG_LONG != 0 {
+        socket.read_u64().await?
+    } else {
+        socket.read_u8().await? as u64
+    };
+    let mut body = vec![0; len as usize];
+    socket.read_exact(&mut body).await?;
+    Ok((flags, body))
+}
+
+/// Reads the frames of the next message, skipping commands.
+async fn read_message(socket: &mut TcpStream) -> Result<Vec<Vec<u8>>> {
+    let mut frames = vec![];
+    loop {
+        let (flags, body) = read_frame(socket).await?;
+        if flags & FLAG_COMMAND != 0 {
+            continue;
+        }
+        frames.push(body);
+        if flags & FLAG_MORE == 0 {
+            return Ok(frames);
+        }
+    }
+}
+
+/// Connects to a ZMQ publisher and returns a stream of the message frames published on
+/// `topic`. The stream ends when the connection is closed.
+pub async fn subscribe(addr: &str, topic: &str) -> Result<BoxStream<'static, Vec<Vec<u8>>>> {
+    let mut socket = TcpStream::connect(addr).await?;
+    socket.write_all(&greeting()).await?;
+    let mut peer = [0; 64];
+    socket.read_exact(&mut peer).await?;
+    ensure!(
+        peer[0] == 0xff && peer[9] == 0x7f && peer[10] >= 3,
+        "unsupported zmtp greeting"
+    );
+    socket.write_all(&ready()).await?;
+    let (flags, body) = read_frame(&mut socket).await?;
+    ensure!(
+        flags & FLAG_COMMAND != 0 && body.get(1..6) == Some(&b"READY"[..]),
+        "zmtp handshake failed"
+    );
+    let mut subscription = vec![1];
+    subscription.extend_from_slice(topic.as_bytes());
+    socket.write_all(&frame(0, &subscription)).await?;
+    let stream = stream::unfold(socket, |mut socket| async move {
+        let message = read_message(&mut socket).await.ok()?;
+        Some((message, socket))
+    });
+    Ok(stream.boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame() {
+        assert_eq!(frame(FLAG_MORE, b"hashblock")[..2], [FLAG_MORE, 9]);
+        let long = frame(0, &[0; 300]);
+        assert_eq!(long[0], FLAG_LONG);
+        assert_eq!(long[1..9], 300u64.to_be_bytes());
+        assert_eq!(long.len(), 309);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() -> Result<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?.to_string();
+        let publisher = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await?;
+            let mut greeting = [0; 64];
+            socket.read_exact(&mut greeting).await?;
+            socket.write_all(&super::greeting()).await?;
+            let (flags, _) = read_frame(&mut socket).await?;
+            assert_eq!(flags, FLAG_COMMAND);
+            socket.write_all(&ready()).await?;
+            let (_, subscription) = read_frame(&mut socket).await?;
+            assert_eq!(subscription, b"\x01hashblock");
+            socket.write_all(&frame(FLAG_MORE, b"hashblock")).await?;
+            socket.write_all(&frame(FLAG_MORE, &[7; 32])).await?;
+            socket.write_all(&frame(0, &0u32.to_le_bytes())).await?;
+            anyhow::Ok(())
+        });
+        let mut stream = subscribe(&addr, "hashblock").await?;
+        let message = stream.next().await.unwrap();
+        assert_eq!(message[0], b"hashblock");
+        assert_eq!(message[1], [7; 32]);
+        publisher.await??;
+        assert!(stream.next().await.is_none());
+        Ok(())
+    }
+}
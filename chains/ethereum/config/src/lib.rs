@@ -15,6 +15,7 @@ pub fn config(network: &str) -> Result<BlockchainConfig> {
         coin: 1,
         bip44: true,
         utxo: false,
+        historical_balance_lookup: true,
         currency_unit: "wei",
         currency_symbol: "ETH",
         currency_decimals: 18,
@@ -24,11 +25,17 @@ pub fn config(network: &str) -> Result<BlockchainConfig> {
             vec![
                 "--dev".into(),
                 "--ipcdisable".into(),
+                "--gcmode=archive".into(),
                 "--http".into(),
                 "--http.addr=0.0.0.0".into(),
                 format!("--http.port={port}"),
                 "--http.vhosts=*".into(),
                 "--http.api=eth,debug,admin,txpool,web3".into(),
+                "--ws".into(),
+                "--ws.addr=0.0.0.0".into(),
+                format!("--ws.port={port}"),
+                "--ws.origins=*".into(),
+                "--ws.api=eth".into(),
             ]
         }),
         node_additional_ports: &[],
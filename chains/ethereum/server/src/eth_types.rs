@@ -5,9 +5,9 @@ pub const FEE_OP_TYPE: &str = "FEE";
 pub const CALL_OP_TYPE: &str = "CALL";
 pub const MINING_REWARD_OP_TYPE: &str = "MINER_REWARD";
 pub const UNCLE_REWARD_OP_TYPE: &str = "UNCLE_REWARD";
-pub const _CALL_CODE_OP_TYPE: &str = "CALLCODE";
-pub const _DELEGATE_CALL_OP_TYPE: &str = "DELEGATECALL";
-pub const _STATIC_CALL_OP_TYPE: &str = "STATICCALL";
+pub const CALL_CODE_OP_TYPE: &str = "CALLCODE";
+pub const DELEGATE_CALL_OP_TYPE: &str = "DELEGATECALL";
+pub const STATIC_CALL_OP_TYPE: &str = "STATICCALL";
 pub const SELF_DESTRUCT_OP_TYPE: &str = "SELFDESTRUCT";
 pub const DESTRUCT_OP_TYPE: &str = "DESTRUCT";
 
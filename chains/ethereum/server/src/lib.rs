@@ -1,18 +1,25 @@
 use anyhow::{bail, Context, Result};
+use eth_types::{
+    CALL_CODE_OP_TYPE, CALL_OP_TYPE, CREATE2_OP_TYPE, CREATE_OP_TYPE, DELEGATE_CALL_OP_TYPE,
+    DESTRUCT_OP_TYPE, FAILURE_STATUS, FEE_OP_TYPE, MINING_REWARD_OP_TYPE, SELF_DESTRUCT_OP_TYPE,
+    STATIC_CALL_OP_TYPE, SUCCESS_STATUS, UNCLE_REWARD_OP_TYPE,
+};
 use ethabi::token::{LenientTokenizer, Tokenizer};
 use ethers::abi::{Detokenize, HumanReadableParser, InvalidOutputType, Token};
 use ethers::prelude::*;
 use ethers::utils::keccak256;
 use ethers::utils::rlp::Encodable;
+use futures::StreamExt;
 use proof::verify_proof;
 use rosetta_config_ethereum::{EthereumMetadata, EthereumMetadataParams};
 use rosetta_server::crypto::address::Address;
 use rosetta_server::crypto::PublicKey;
 use rosetta_server::types::{
-    Block, BlockIdentifier, CallRequest, Coin, PartialBlockIdentifier, Transaction,
-    TransactionIdentifier,
+    BalanceExemption, Block, BlockIdentifier, CallRequest, Coin, OperationStatus,
+    PartialBlockIdentifier, Peer, Transaction, TransactionIdentifier,
 };
-use rosetta_server::{BlockchainClient, BlockchainConfig};
+use rosetta_server::{BlockStream, BlockchainClient, BlockchainConfig};
+use rosetta_tx_ethereum::EthereumTransactionBuilder;
 use serde_json::{json, Value};
 use std::str::FromStr;
 use std::sync::Arc;
@@ -21,8 +28,23 @@ mod eth_types;
 mod proof;
 mod utils;
 
+/// Query types accepted as the last segment of a `/call` method.
+const CALL_METHODS: &[&str] = &["call", "storage", "storage_proof", "transaction_receipt"];
+
+/// Returns true if the node rejected the `finalized` block tag, either because it predates
+/// the merge and can't parse the tag or because it doesn't track a finalized block.
+fn is_finalized_tag_unsupported(err: &ProviderError) -> bool {
+    /// JSON-RPC error code of invalid method parameters.
+    const INVALID_PARAMS: i64 = -32602;
+    let Some(err) = RpcError::as_error_response(err) else {
+        return false;
+    };
+    err.code == INVALID_PARAMS || err.message.contains("finalized")
+}
+
 pub struct EthereumClient {
     config: BlockchainConfig,
+    addr: String,
     client: Arc<Provider<Http>>,
     genesis_block: BlockIdentifier,
 }
@@ -31,9 +53,14 @@ pub struct EthereumClient {
 impl BlockchainClient for EthereumClient {
     type MetadataParams = EthereumMetadataParams;
     type Metadata = EthereumMetadata;
+    type TransactionBuilder = EthereumTransactionBuilder;
+
+    fn create_config(network: &str) -> Result<BlockchainConfig> {
+        rosetta_config_ethereum::config(network)
+    }
 
     async fn new(network: &str, addr: &str) -> Result<Self> {
-        let config = rosetta_config_ethereum::config(network)?;
+        let config = Self::create_config(network)?;
         let client = Arc::new(Provider::<Http>::try_from(format!("http://{addr}"))?);
         let genesis = client
             .get_block(0)
@@ -45,6 +72,7 @@ impl BlockchainClient for EthereumClient {
         };
         Ok(Self {
             config,
+            addr: addr.into(),
             client,
             genesis_block,
         })
@@ -58,6 +86,49 @@ impl BlockchainClient for EthereumClient {
         &self.genesis_block
     }
 
+    fn operation_statuses(&self) -> Vec<OperationStatus> {
+        vec![
+            OperationStatus {
+                status: SUCCESS_STATUS.into(),
+                successful: true,
+            },
+            OperationStatus {
+                status: FAILURE_STATUS.into(),
+                successful: false,
+            },
+        ]
+    }
+
+    fn operation_types(&self) -> Vec<String> {
+        [
+            FEE_OP_TYPE,
+            CALL_OP_TYPE,
+            CALL_CODE_OP_TYPE,
+            DELEGATE_CALL_OP_TYPE,
+            STATIC_CALL_OP_TYPE,
+            CREATE_OP_TYPE,
+            CREATE2_OP_TYPE,
+            SELF_DESTRUCT_OP_TYPE,
+            DESTRUCT_OP_TYPE,
+            MINING_REWARD_OP_TYPE,
+            UNCLE_REWARD_OP_TYPE,
+        ]
+        .into_iter()
+        .map(Into::into)
+        .collect()
+    }
+
+    fn call_methods(&self) -> Vec<String> {
+        CALL_METHODS
+            .iter()
+            .map(|method| method.to_string())
+            .collect()
+    }
+
+    fn balance_exemptions(&self) -> Vec<BalanceExemption> {
+        vec![]
+    }
+
     async fn node_version(&self) -> Result<String> {
         Ok(self.client.client_version().await?)
     }
@@ -75,6 +146,79 @@ impl BlockchainClient for EthereumClient {
         })
     }
 
+    async fn finalized_block(&self) -> Result<BlockIdentifier> {
+        let block = match self.client.get_block(BlockNumber::Finalized).await {
+            Ok(Some(block)) => block,
+            // nodes without a consensus client, like dev nodes, don't know a finalized block
+            Ok(None) => return Ok(self.genesis_block.clone()),
+            Err(err) if is_finalized_tag_unsupported(&err) => {
+                return Ok(self.genesis_block.clone())
+            }
+            Err(err) => return Err(err.into()),
+        };
+        Ok(BlockIdentifier {
+            index: block.number.context("missing block number")?.as_u64(),
+            hash: hex::encode(block.hash.context("missing block hash")?),
+        })
+    }
+
+    async fn block_identifier(
+        &self,
+        block_identifier: &PartialBlockIdentifier,
+    ) -> Result<BlockIdentifier> {
+        let block_id = if let Some(hash) = block_identifier.hash.as_ref() {
+            BlockId::Hash(H256::from_str(hash)?)
+        } else {
+            let index = if let Some(index) = block_identifier.index {
+                BlockNumber::Number(U64::from(index))
+            } else {
+                BlockNumber::Latest
+            };
+            BlockId::Number(index)
+        };
+        let block = self
+            .client
+            .get_block(block_id)
+            .await?
+            .context("block not found")?;
+        Ok(BlockIdentifier {
+            index: block.number.context("missing block number")?.as_u64(),
+            hash: hex::encode(block.hash.context("missing block hash")?),
+        })
+    }
+
+    async fn block_timestamp(&self, block: &BlockIdentifier) -> Result<i64> {
+        let block = self
+            .client
+            .get_block(BlockId::Hash(H256::from_str(&block.hash)?))
+            .await?
+            .context("block not found")?;
+        Ok(block.timestamp.as_u64() as i64 * 1000)
+    }
+
+    async fn peers(&self) -> Result<Vec<Peer>> {
+        Ok(self
+            .client
+            .peers()
+            .await?
+            .into_iter()
+            .map(|peer| Peer {
+                peer_id: peer.id,
+                metadata: Some(json!({
+                    "name": peer.name,
+                    "enode": peer.enode,
+                })),
+            })
+            .collect())
+    }
+
+    async fn syncing(&self) -> Result<bool> {
+        Ok(!matches!(
+            self.client.syncing().await?,
+            SyncingStatus::IsFalse
+        ))
+    }
+
     async fn balance(&self, address: &Address, block: &BlockIdentifier) -> Result<u128> {
         let block = hex::decode(&block.hash)?
             .try_into()
@@ -223,6 +367,37 @@ impl BlockchainClient for EthereumClient {
         Ok(transaction)
     }
 
+    /// Lists the executable transactions of the pool followed by the queued ones, which
+    /// wait for a nonce gap to be filled.
+    async fn mempool(&self) -> Result<Vec<TransactionIdentifier>> {
+        let content = self.client.txpool_content().await?;
+        Ok(content
+            .pending
+            .values()
+            .chain(content.queued.values())
+            .flat_map(|txs| txs.values())
+            .map(|tx| TransactionIdentifier {
+                hash: hex::encode(tx.hash),
+            })
+            .collect())
+    }
+
+    async fn mempool_transaction(&self, tx: &TransactionIdentifier) -> Result<Transaction> {
+        let tx_id = H256::from_str(&tx.hash)?;
+        let transaction = self
+            .client
+            .get_transaction(tx_id)
+            .await?
+            .context("transaction not found")?;
+        if transaction.block_hash.is_some() {
+            anyhow::bail!("transaction not in mempool");
+        }
+        Ok(crate::utils::get_pending_transaction(
+            self.config(),
+            &transaction,
+        ))
+    }
+
     async fn call(&self, req: &CallRequest) -> Result<Value> {
         let call_details = req.method.split('-').collect::<Vec<&str>>();
         if call_details.len() != 3 {
@@ -334,6 +509,25 @@ impl BlockchainClient for EthereumClient {
             }
         }
     }
+
+    async fn listen(&self) -> Result<Option<BlockStream>> {
+        let ws = Ws::connect(format!("ws://{}", self.addr)).await?;
+        let id: U256 = JsonRpcClient::request(&ws, "eth_subscribe", ["newHeads"]).await?;
+        let heads = ws.subscribe(id)?;
+        let stream = heads.filter_map(move |header| {
+            // keeps the connection open for the lifetime of the stream
+            let _ws = &ws;
+            let head = serde_json::from_str::<ethers::types::Block<H256>>(header.get())
+                .ok()
+                .and_then(|block| Some((block.number?, block.hash?)))
+                .map(|(number, hash)| BlockIdentifier {
+                    index: number.as_u64(),
+                    hash: hex::encode(hash),
+                });
+            futures::future::ready(head)
+        });
+        Ok(Some(stream.boxed()))
+    }
 }
 
 #[cfg(test)]
@@ -371,6 +565,18 @@ mod tests {
         rosetta_server::tests::account(config).await
     }
 
+    #[tokio::test]
+    async fn test_construction_derive() -> Result<()> {
+        let config = rosetta_config_ethereum::config("dev")?;
+        rosetta_server::tests::construction_derive(config).await
+    }
+
+    #[tokio::test]
+    async fn test_mempool() -> Result<()> {
+        let config = rosetta_config_ethereum::config("dev")?;
+        rosetta_server::tests::mempool(config).await
+    }
+
     #[tokio::test]
     async fn test_construction() -> Result<()> {
         let config = rosetta_config_ethereum::config("dev")?;
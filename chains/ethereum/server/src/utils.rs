@@ -68,6 +68,81 @@ pub async fn get_transaction<T>(
     })
 }
 
+/// Pending transactions have neither a receipt nor a trace, so only the value transfer is known.
+pub fn get_pending_transaction(
+    config: &BlockchainConfig,
+    tx: &Transaction,
+) -> rosetta_types::Transaction {
+    let currency = config.currency();
+    let op_type = if tx.to.is_some() {
+        CALL_OP_TYPE
+    } else {
+        CREATE_OP_TYPE
+    };
+    let amount = |value: String| {
+        if tx.value.is_zero() {
+            None
+        } else {
+            Some(Amount {
+                value,
+                currency: currency.clone(),
+                metadata: None,
+            })
+        }
+    };
+    let mut operations = vec![Operation {
+        operation_identifier: OperationIdentifier {
+            index: 0,
+            network_index: None,
+        },
+        related_operations: None,
+        r#type: op_type.into(),
+        status: None,
+        account: Some(AccountIdentifier {
+            address: to_checksum(&tx.from, None),
+            sub_account: None,
+            metadata: None,
+        }),
+        amount: amount(format!("-{}", tx.value)),
+        coin_change: None,
+        metadata: None,
+    }];
+    if let Some(to) = tx.to.as_ref() {
+        operations.push(Operation {
+            operation_identifier: OperationIdentifier {
+                index: 1,
+                network_index: None,
+            },
+            related_operations: Some(vec![OperationIdentifier {
+                index: 0,
+                network_index: None,
+            }]),
+            r#type: op_type.into(),
+            status: None,
+            account: Some(AccountIdentifier {
+                address: to_checksum(to, None),
+                sub_account: None,
+                metadata: None,
+            }),
+            amount: amount(format!("{}", tx.value)),
+            coin_change: None,
+            metadata: None,
+        });
+    }
+    rosetta_types::Transaction {
+        transaction_identifier: TransactionIdentifier {
+            hash: hex::encode(tx.hash),
+        },
+        operations,
+        related_transactions: None,
+        metadata: Some(json!({
+            "gas_limit" : tx.gas,
+            "gas_price": tx.gas_price,
+            "nonce": tx.nonce,
+        })),
+    }
+}
+
 fn get_fee_operations<T>(
     block: &Block<T>,
     tx: &Transaction,
@@ -1,13 +1,20 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use ethabi::token::{LenientTokenizer, Tokenizer};
 use ethers_core::abi::HumanReadableParser;
-use ethers_core::types::{Eip1559TransactionRequest, NameOrAddress, Signature, H160};
+use ethers_core::types::{Bytes, Eip1559TransactionRequest, NameOrAddress, Signature, H160};
+use ethers_core::utils::rlp::{self, RlpStream};
+use ethers_core::utils::to_checksum;
 use rosetta_config_ethereum::{EthereumMetadata, EthereumMetadataParams};
 use rosetta_core::crypto::address::Address;
-use rosetta_core::crypto::SecretKey;
-use rosetta_core::{BlockchainConfig, TransactionBuilder};
+use rosetta_core::crypto::{PublicKey, SecretKey, Signature as CryptoSignature};
+use rosetta_core::types::{AccountIdentifier, Amount, Operation, OperationIdentifier};
+use rosetta_core::{BlockchainConfig, ParsedTransaction, TransactionBuilder, UnsignedTransaction};
+use serde_json::json;
 use sha3::{Digest, Keccak256};
 
+const CALL_OP_TYPE: &str = "CALL";
+const CREATE_OP_TYPE: &str = "CREATE";
+
 pub use ethers_core::types::U256;
 
 #[derive(Default)]
@@ -57,19 +64,17 @@ impl TransactionBuilder for EthereumTransactionBuilder {
         })
     }
 
-    fn create_and_sign(
+    fn create_unsigned(
         &self,
         config: &BlockchainConfig,
         metadata_params: &Self::MetadataParams,
         metadata: &Self::Metadata,
-        secret_key: &SecretKey,
-    ) -> Vec<u8> {
-        let from = secret_key
-            .public_key()
+        public_key: &PublicKey,
+    ) -> Result<UnsignedTransaction> {
+        let from = public_key
             .to_address(config.address_format)
             .address()
-            .parse()
-            .unwrap();
+            .parse()?;
         let to: Option<NameOrAddress> = if metadata_params.destination.len() >= 20 {
             Some(H160::from_slice(&metadata_params.destination).into())
         } else {
@@ -87,19 +92,238 @@ impl TransactionBuilder for EthereumTransactionBuilder {
             max_fee_per_gas: Some(U256(metadata.max_fee_per_gas)),
             chain_id: Some(metadata.chain_id.into()),
         };
-        let mut hasher = Keccak256::new();
-        hasher.update([0x02]);
-        hasher.update(tx.rlp());
-        let hash = hasher.finalize();
-        let signature = secret_key.sign_prehashed(&hash).unwrap().to_bytes();
+        let envelope = typed_envelope(tx.rlp());
+        let signing_payload = Keccak256::digest(&envelope).to_vec();
+        // the sender isn't part of the unsigned envelope, keep it next to it
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&from);
+        stream.append(&envelope);
+        Ok(UnsignedTransaction {
+            transaction: stream.out().to_vec(),
+            signing_payload,
+        })
+    }
+
+    fn combine(
+        &self,
+        _config: &BlockchainConfig,
+        transaction: &[u8],
+        signature: &CryptoSignature,
+    ) -> Result<Vec<u8>> {
+        let tx = decode_unsigned(transaction)?;
+        let signature = signature.to_bytes();
+        anyhow::ensure!(signature.len() == 65, "expected a recoverable signature");
         let rlp = tx.rlp_signed(&Signature {
             r: U256::from_big_endian(&signature[..32]),
             s: U256::from_big_endian(&signature[32..64]),
             v: signature[64] as _,
         });
-        let mut tx = Vec::with_capacity(rlp.len() + 1);
-        tx.push(0x02);
-        tx.extend(rlp);
-        tx
+        Ok(typed_envelope(rlp))
+    }
+
+    fn parse(
+        &self,
+        config: &BlockchainConfig,
+        transaction: &[u8],
+        signed: bool,
+    ) -> Result<ParsedTransaction> {
+        let tx = if signed {
+            let Some((0x02, rlp)) = transaction.split_first() else {
+                anyhow::bail!("expected an eip1559 transaction");
+            };
+            let (tx, _) = Eip1559TransactionRequest::decode_signed_rlp(&rlp::Rlp::new(rlp))?;
+            tx
+        } else {
+            decode_unsigned(transaction)?
+        };
+        let from = tx.from.context("missing sender")?;
+        let value = tx.value.unwrap_or_default();
+        let amount = |value: String| {
+            Some(Amount {
+                value,
+                currency: config.currency(),
+                metadata: None,
+            })
+        };
+        let mut operations = vec![];
+        let to = match tx.to.as_ref() {
+            Some(NameOrAddress::Address(to)) => Some(*to),
+            Some(NameOrAddress::Name(_)) => anyhow::bail!("unexpected ens name"),
+            None => None,
+        };
+        operations.push(Operation {
+            operation_identifier: OperationIdentifier::new(0),
+            r#type: if to.is_some() {
+                CALL_OP_TYPE
+            } else {
+                CREATE_OP_TYPE
+            }
+            .into(),
+            account: Some(AccountIdentifier::new(to_checksum(&from, None))),
+            amount: amount(format!("-{value}")),
+            ..Default::default()
+        });
+        if let Some(to) = to {
+            operations.push(Operation {
+                operation_identifier: OperationIdentifier::new(1),
+                related_operations: Some(vec![OperationIdentifier::new(0)]),
+                r#type: CALL_OP_TYPE.into(),
+                account: Some(AccountIdentifier::new(to_checksum(&to, None))),
+                amount: amount(value.to_string()),
+                ..Default::default()
+            });
+        }
+        if value.is_zero() {
+            for op in &mut operations {
+                op.amount = None;
+            }
+        }
+        Ok(ParsedTransaction {
+            operations,
+            signers: if signed {
+                vec![to_checksum(&from, None)]
+            } else {
+                vec![]
+            },
+            signed,
+            metadata: Some(json!({
+                "chain_id": tx.chain_id.unwrap_or_default(),
+                "nonce": tx.nonce.unwrap_or_default(),
+                "max_priority_fee_per_gas": tx.max_priority_fee_per_gas.unwrap_or_default(),
+                "max_fee_per_gas": tx.max_fee_per_gas.unwrap_or_default(),
+                "gas_limit": tx.gas.unwrap_or_default(),
+                "data": hex::encode(tx.data.unwrap_or_default()),
+            })),
+        })
+    }
+
+    fn create_and_sign(
+        &self,
+        config: &BlockchainConfig,
+        metadata_params: &Self::MetadataParams,
+        metadata: &Self::Metadata,
+        secret_key: &SecretKey,
+    ) -> Result<Vec<u8>> {
+        let unsigned =
+            self.create_unsigned(config, metadata_params, metadata, &secret_key.public_key())?;
+        let signature = secret_key.sign_prehashed(&unsigned.signing_payload)?;
+        self.combine(config, &unsigned.transaction, &signature)
+    }
+
+    fn hash(&self, _config: &BlockchainConfig, transaction: &[u8]) -> Result<Vec<u8>> {
+        let Some((0x02, rlp)) = transaction.split_first() else {
+            anyhow::bail!("expected an eip1559 transaction");
+        };
+        Eip1559TransactionRequest::decode_signed_rlp(&rlp::Rlp::new(rlp))?;
+        Ok(Keccak256::digest(transaction).to_vec())
+    }
+}
+
+fn typed_envelope(rlp: Bytes) -> Vec<u8> {
+    let mut envelope = Vec::with_capacity(rlp.len() + 1);
+    envelope.push(0x02);
+    envelope.extend(rlp);
+    envelope
+}
+
+fn decode_unsigned(transaction: &[u8]) -> Result<Eip1559TransactionRequest> {
+    let stream = rlp::Rlp::new(transaction);
+    let from: H160 = stream.val_at(0)?;
+    let envelope: Vec<u8> = stream.val_at(1)?;
+    let Some((0x02, rlp)) = envelope.split_first() else {
+        anyhow::bail!("expected an eip1559 transaction");
+    };
+    let mut tx: Eip1559TransactionRequest = rlp::decode(rlp)?;
+    tx.from = Some(from);
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers_core::types::transaction::eip2718::TypedTransaction;
+    use rosetta_core::crypto::Algorithm;
+
+    #[test]
+    fn test_detached_signing() -> Result<()> {
+        let config = rosetta_config_ethereum::config("dev")?;
+        let secret_key = SecretKey::from_bytes(Algorithm::EcdsaRecoverableSecp256k1, &[1; 32])?;
+        let address = secret_key.public_key().to_address(config.address_format);
+        let builder = EthereumTransactionBuilder;
+        let metadata_params = builder.transfer(&address, 1000)?;
+        let metadata = EthereumMetadata {
+            chain_id: 1337,
+            nonce: 1,
+            max_priority_fee_per_gas: U256::from(1).0,
+            max_fee_per_gas: U256::from(2).0,
+            gas_limit: U256::from(21000).0,
+        };
+
+        let unsigned = builder.create_unsigned(
+            &config,
+            &metadata_params,
+            &metadata,
+            &secret_key.public_key(),
+        )?;
+        let signature = secret_key.sign_prehashed(&unsigned.signing_payload)?;
+        let signed = builder.combine(&config, &unsigned.transaction, &signature)?;
+        assert_eq!(
+            signed,
+            builder.create_and_sign(&config, &metadata_params, &metadata, &secret_key)?
+        );
+
+        let (tx, signature) = TypedTransaction::decode_signed(&rlp::Rlp::new(&signed))?;
+        assert_eq!(
+            format!("{:?}", tx.from().unwrap()),
+            address.address().to_lowercase()
+        );
+        assert_eq!(
+            builder.hash(&config, &signed)?,
+            tx.hash(&signature).as_bytes()
+        );
+        assert!(builder.hash(&config, &unsigned.transaction).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let config = rosetta_config_ethereum::config("dev")?;
+        let secret_key = SecretKey::from_bytes(Algorithm::EcdsaRecoverableSecp256k1, &[1; 32])?;
+        let from = secret_key.public_key().to_address(config.address_format);
+        let to = SecretKey::from_bytes(Algorithm::EcdsaRecoverableSecp256k1, &[2; 32])?
+            .public_key()
+            .to_address(config.address_format);
+        let builder = EthereumTransactionBuilder;
+        let metadata_params = builder.transfer(&to, 1000)?;
+        let metadata = EthereumMetadata {
+            chain_id: 1337,
+            nonce: 1,
+            max_priority_fee_per_gas: U256::from(1).0,
+            max_fee_per_gas: U256::from(2).0,
+            gas_limit: U256::from(21000).0,
+        };
+        let unsigned = builder.create_unsigned(
+            &config,
+            &metadata_params,
+            &metadata,
+            &secret_key.public_key(),
+        )?;
+        let signed = builder.create_and_sign(&config, &metadata_params, &metadata, &secret_key)?;
+
+        let parsed = builder.parse(&config, &unsigned.transaction, false)?;
+        assert!(!parsed.signed);
+        assert!(parsed.signers.is_empty());
+        assert_eq!(parsed.operations.len(), 2);
+        let ops = &parsed.operations;
+        assert_eq!(ops[0].account.as_ref().unwrap().address, from.address());
+        assert_eq!(ops[0].amount.as_ref().unwrap().value, "-1000");
+        assert_eq!(ops[1].account.as_ref().unwrap().address, to.address());
+        assert_eq!(ops[1].amount.as_ref().unwrap().value, "1000");
+
+        let parsed_signed = builder.parse(&config, &signed, true)?;
+        assert!(parsed_signed.signed);
+        assert_eq!(parsed_signed.signers, vec![from.address().to_string()]);
+        assert_eq!(parsed_signed.operations, parsed.operations);
+        Ok(())
     }
 }
@@ -27,6 +27,7 @@ pub fn config(network: &str) -> Result<BlockchainConfig> {
         coin: 1,
         bip44: false,
         utxo: false,
+        historical_balance_lookup: true,
         currency_unit: "planck",
         currency_symbol: if kusama { "KSM" } else { "DOT" },
         currency_decimals: if kusama { 12 } else { 10 },
@@ -39,6 +40,7 @@ pub fn config(network: &str) -> Result<BlockchainConfig> {
                 "--ws-external".into(),
                 format!("--ws-port={port}"),
                 "--alice".into(),
+                "--state-pruning=archive".into(),
                 "--tmp".into(),
             ]
         }),
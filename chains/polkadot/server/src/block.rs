@@ -13,6 +13,13 @@ use subxt::ext::scale_value::{Composite, Primitive, ValueDef};
 use subxt::utils::H256;
 use subxt::{Config, OnlineClient};
 
+pub const SUCCESS_STATUS: &str = "SUCCESS";
+pub const FAILURE_STATUS: &str = "FAILURE";
+
+/// Event emitted when an extrinsic is dispatched unsuccessfully. Only the fee events are
+/// emitted besides it, so it is the only operation of a failed extrinsic to fail.
+const EXTRINSIC_FAILED: &str = "System.ExtrinsicFailed";
+
 pub async fn get_transaction<T: Config<Hash = H256>>(
     config: &BlockchainConfig,
     extrinsic: &Extrinsic<'_, T, OnlineClient<T>>,
@@ -29,6 +36,12 @@ pub async fn get_transaction<T: Config<Hash = H256>>(
         }
         let op_metadata = Value::Array(fields);
 
+        let status = if event_parsed_data.event_type == EXTRINSIC_FAILED {
+            FAILURE_STATUS
+        } else {
+            SUCCESS_STATUS
+        };
+
         let op_from: Option<AccountIdentifier> =
             event_parsed_data.from.map(|address| AccountIdentifier {
                 address,
@@ -50,7 +63,7 @@ pub async fn get_transaction<T: Config<Hash = H256>>(
             },
             related_operations: None,
             r#type: event_parsed_data.event_type.clone(),
-            status: None,
+            status: Some(status.into()),
             account: op_from,
             amount: op_neg_amount,
             coin_change: None,
@@ -66,7 +79,7 @@ pub async fn get_transaction<T: Config<Hash = H256>>(
                 },
                 related_operations: None,
                 r#type: event_parsed_data.event_type,
-                status: None,
+                status: Some(status.into()),
                 account: Some(AccountIdentifier {
                     address: to,
                     sub_account: None,
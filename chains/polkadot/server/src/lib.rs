@@ -1,19 +1,24 @@
 use anyhow::{Context, Result};
+use futures::StreamExt;
 use parity_scale_codec::{Decode, Encode};
 use rosetta_config_polkadot::{PolkadotMetadata, PolkadotMetadataParams};
 use rosetta_server::crypto::address::Address;
 use rosetta_server::crypto::PublicKey;
 use rosetta_server::types::{
-    Block, BlockIdentifier, CallRequest, Coin, PartialBlockIdentifier, Transaction,
-    TransactionIdentifier,
+    BalanceExemption, Block, BlockIdentifier, CallRequest, Coin, OperationStatus,
+    PartialBlockIdentifier, Peer, Transaction, TransactionIdentifier,
 };
-use rosetta_server::{BlockchainClient, BlockchainConfig};
-use serde_json::Value;
+use rosetta_server::{BlockStream, BlockchainClient, BlockchainConfig};
+use rosetta_tx_polkadot::PolkadotTransactionBuilder;
+use scale_info::TypeDef;
+use serde::Deserialize;
+use serde_json::{json, Value};
 use sp_keyring::AccountKeyring;
 use std::time::Duration;
 use subxt::config::{Hasher, Header};
 use subxt::metadata::DecodeStaticType;
-use subxt::rpc::types::BlockNumber;
+use subxt::rpc::rpc_params;
+use subxt::rpc::types::{BlockNumber, Bytes};
 use subxt::storage::address::{StorageHasher, StorageMapKey, Yes};
 use subxt::storage::StaticStorageAddress;
 use subxt::tx::{PairSigner, StaticTxPayload, SubmittableExtrinsic};
@@ -23,6 +28,9 @@ use subxt::{Config, OnlineClient, PolkadotConfig};
 mod block;
 mod call;
 
+/// Query types accepted as the last segment of a `/call` method.
+const CALL_METHODS: &[&str] = &["constant", "storage"];
+
 pub struct PolkadotClient {
     config: BlockchainConfig,
     client: OnlineClient<PolkadotConfig>,
@@ -30,6 +38,41 @@ pub struct PolkadotClient {
 }
 
 impl PolkadotClient {
+    /// Value of `Timestamp.Now` at the given block in milliseconds.
+    async fn timestamp(&self, block_hash: H256) -> Result<u64> {
+        let timestamp_now_address =
+            StaticStorageAddress::<DecodeStaticType<u64>, Yes, Yes, ()>::new(
+                "Timestamp",
+                "Now",
+                vec![],
+                [0; 32],
+            )
+            .unvalidated();
+        Ok(self
+            .client
+            .storage()
+            .at(Some(block_hash))
+            .await?
+            .fetch_or_default(&timestamp_now_address)
+            .await?)
+    }
+
+    /// Extrinsics in the transaction pool with their hashes.
+    async fn pending_extrinsics(&self) -> Result<Vec<(H256, Vec<u8>)>> {
+        let extrinsics: Vec<Bytes> = self
+            .client
+            .rpc()
+            .request("author_pendingExtrinsics", rpc_params![])
+            .await?;
+        Ok(extrinsics
+            .into_iter()
+            .map(|extrinsic| {
+                let hash = <PolkadotConfig as Config>::Hasher::hash(&extrinsic.0);
+                (hash, extrinsic.0)
+            })
+            .collect())
+    }
+
     async fn account_info(
         &self,
         address: &Address,
@@ -79,9 +122,14 @@ impl PolkadotClient {
 impl BlockchainClient for PolkadotClient {
     type MetadataParams = PolkadotMetadataParams;
     type Metadata = PolkadotMetadata;
+    type TransactionBuilder = PolkadotTransactionBuilder;
+
+    fn create_config(network: &str) -> Result<BlockchainConfig> {
+        rosetta_config_polkadot::config(network)
+    }
 
     async fn new(network: &str, addr: &str) -> Result<Self> {
-        let config = rosetta_config_polkadot::config(network)?;
+        let config = Self::create_config(network)?;
         let client = OnlineClient::<PolkadotConfig>::from_url(format!("ws://{addr}")).await?;
         let genesis = client.genesis_hash();
         let genesis_block = BlockIdentifier {
@@ -103,6 +151,50 @@ impl BlockchainClient for PolkadotClient {
         &self.genesis_block
     }
 
+    fn operation_statuses(&self) -> Vec<OperationStatus> {
+        vec![
+            OperationStatus {
+                status: block::SUCCESS_STATUS.into(),
+                successful: true,
+            },
+            OperationStatus {
+                status: block::FAILURE_STATUS.into(),
+                successful: false,
+            },
+        ]
+    }
+
+    /// Operations are derived from events, so every runtime event is an operation type.
+    fn operation_types(&self) -> Vec<String> {
+        let metadata = self.client.metadata();
+        let mut operation_types = vec![];
+        for pallet in &metadata.runtime_metadata().pallets {
+            let Some(event) = pallet.event.as_ref() else {
+                continue;
+            };
+            let Some(TypeDef::Variant(variant)) =
+                metadata.resolve_type(event.ty.id).map(|ty| &ty.type_def)
+            else {
+                continue;
+            };
+            for event in &variant.variants {
+                operation_types.push(format!("{}.{}", pallet.name, event.name));
+            }
+        }
+        operation_types
+    }
+
+    fn call_methods(&self) -> Vec<String> {
+        CALL_METHODS
+            .iter()
+            .map(|method| method.to_string())
+            .collect()
+    }
+
+    fn balance_exemptions(&self) -> Vec<BalanceExemption> {
+        vec![]
+    }
+
     async fn node_version(&self) -> Result<String> {
         Ok(self.client.rpc().system_version().await?)
     }
@@ -122,6 +214,74 @@ impl BlockchainClient for PolkadotClient {
         })
     }
 
+    /// Latest block finalized by GRANDPA.
+    async fn finalized_block(&self) -> Result<BlockIdentifier> {
+        let hash = self.client.rpc().finalized_head().await?;
+        let header = self
+            .client
+            .rpc()
+            .header(Some(hash))
+            .await?
+            .context("no finalized block")?;
+        Ok(BlockIdentifier {
+            index: header.number as _,
+            hash: hex::encode(hash.as_ref()),
+        })
+    }
+
+    async fn block_identifier(
+        &self,
+        block_identifier: &PartialBlockIdentifier,
+    ) -> Result<BlockIdentifier> {
+        let block_hash: H256 = if let Some(hash) = block_identifier.hash.as_ref() {
+            hash.parse()?
+        } else {
+            self.client
+                .rpc()
+                .block_hash(block_identifier.index.map(BlockNumber::from))
+                .await?
+                .context("block not found")?
+        };
+        let header = self
+            .client
+            .rpc()
+            .header(Some(block_hash))
+            .await?
+            .context("block not found")?;
+        Ok(BlockIdentifier {
+            index: header.number as _,
+            hash: hex::encode(block_hash.as_ref()),
+        })
+    }
+
+    async fn block_timestamp(&self, block: &BlockIdentifier) -> Result<i64> {
+        let timestamp = self.timestamp(block.hash.parse()?).await?;
+        Ok(timestamp as i64)
+    }
+
+    async fn peers(&self) -> Result<Vec<Peer>> {
+        let peers: Vec<PeerInfo> = self
+            .client
+            .rpc()
+            .request("system_peers", rpc_params![])
+            .await?;
+        Ok(peers
+            .into_iter()
+            .map(|peer| Peer {
+                peer_id: peer.peer_id,
+                metadata: Some(json!({
+                    "roles": peer.roles,
+                    "best_hash": peer.best_hash,
+                    "best_number": peer.best_number,
+                })),
+            })
+            .collect())
+    }
+
+    async fn syncing(&self) -> Result<bool> {
+        Ok(self.client.rpc().system_health().await?.is_syncing)
+    }
+
     async fn balance(&self, address: &Address, block: &BlockIdentifier) -> Result<u128> {
         let account_info = self.account_info(address, Some(block)).await?;
         Ok(account_info.data.free)
@@ -198,18 +358,7 @@ impl BlockchainClient for PolkadotClient {
                 .context("block not found")?
         };
         let block = self.client.blocks().at(Some(block_hash)).await?;
-        let timestamp_now_address =
-            StaticStorageAddress::<DecodeStaticType<u64>, Yes, Yes, ()>::new(
-                "Timestamp",
-                "Now",
-                vec![],
-                [0; 32],
-            )
-            .unvalidated();
-        let timestamp = block
-            .storage()
-            .fetch_or_default(&timestamp_now_address)
-            .await?;
+        let timestamp = self.timestamp(block_hash).await?;
         let body = block.body().await?;
         let mut transactions = vec![];
         for extrinsic in body.extrinsics() {
@@ -254,6 +403,41 @@ impl BlockchainClient for PolkadotClient {
         crate::block::get_transaction(self.config(), &extrinsic).await
     }
 
+    async fn mempool(&self) -> Result<Vec<TransactionIdentifier>> {
+        Ok(self
+            .pending_extrinsics()
+            .await?
+            .into_iter()
+            .map(|(hash, _)| TransactionIdentifier {
+                hash: hex::encode(hash),
+            })
+            .collect())
+    }
+
+    async fn mempool_transaction(&self, tx: &TransactionIdentifier) -> Result<Transaction> {
+        let transaction_hash: H256 = tx.hash.parse()?;
+        let (_, extrinsic) = self
+            .pending_extrinsics()
+            .await?
+            .into_iter()
+            .find(|(hash, _)| *hash == transaction_hash)
+            .context("transaction not found")?;
+        let metadata = self.client.metadata();
+        let pallet = metadata.pallet("Balances")?;
+        let transfer_call_index = (pallet.index(), pallet.call_index("transfer")?);
+        let parsed = PolkadotTransactionBuilder.parse_extrinsic(
+            self.config(),
+            &extrinsic,
+            transfer_call_index,
+        )?;
+        Ok(Transaction {
+            transaction_identifier: tx.clone(),
+            operations: parsed.operations,
+            related_transactions: None,
+            metadata: parsed.metadata,
+        })
+    }
+
     async fn call(&self, request: &CallRequest) -> Result<Value> {
         let call_details = request.method.split('-').collect::<Vec<&str>>();
         if call_details.len() != 3 {
@@ -278,6 +462,27 @@ impl BlockchainClient for PolkadotClient {
             }
         }
     }
+
+    async fn listen(&self) -> Result<Option<BlockStream>> {
+        let blocks = self.client.blocks().subscribe_best().await?;
+        let stream = blocks.filter_map(|block| {
+            let head = block.ok().map(|block| BlockIdentifier {
+                index: block.number() as _,
+                hash: hex::encode(block.hash()),
+            });
+            futures::future::ready(head)
+        });
+        Ok(Some(stream.boxed()))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PeerInfo {
+    peer_id: String,
+    roles: String,
+    best_hash: H256,
+    best_number: u64,
 }
 
 #[derive(Decode, Encode, Debug)]
@@ -332,6 +537,18 @@ mod tests {
         rosetta_server::tests::account(config).await
     }
 
+    #[tokio::test]
+    async fn test_construction_derive() -> Result<()> {
+        let config = rosetta_config_polkadot::config("dev")?;
+        rosetta_server::tests::construction_derive(config).await
+    }
+
+    #[tokio::test]
+    async fn test_mempool() -> Result<()> {
+        let config = rosetta_config_polkadot::config("dev")?;
+        rosetta_server::tests::mempool(config).await
+    }
+
     #[tokio::test]
     async fn test_construction() -> Result<()> {
         let config = rosetta_config_polkadot::config("dev")?;
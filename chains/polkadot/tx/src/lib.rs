@@ -2,8 +2,10 @@ use anyhow::{bail, Context, Result};
 use parity_scale_codec::{Compact, Decode, Encode};
 use rosetta_config_polkadot::{PolkadotMetadata, PolkadotMetadataParams};
 use rosetta_core::crypto::address::Address;
-use rosetta_core::crypto::SecretKey;
-use rosetta_core::{BlockchainConfig, TransactionBuilder};
+use rosetta_core::crypto::{PublicKey, SecretKey, Signature};
+use rosetta_core::types::{AccountIdentifier, Amount, Operation, OperationIdentifier};
+use rosetta_core::{BlockchainConfig, ParsedTransaction, TransactionBuilder, UnsignedTransaction};
+use serde_json::json;
 
 #[derive(Debug, Decode, Encode)]
 struct AccountId32([u8; 32]);
@@ -13,11 +15,11 @@ enum MultiAddress {
     Id(AccountId32),
 }
 
-#[derive(Encode)]
+#[derive(Decode, Encode)]
 enum MultiSignature {
-    #[allow(unused)]
     Ed25519([u8; 64]),
     Sr25519([u8; 64]),
+    Ecdsa([u8; 65]),
 }
 
 #[derive(Encode)]
@@ -25,6 +27,142 @@ enum Era {
     Immortal,
 }
 
+impl Era {
+    /// Decodes an era, returns `None` for mortal eras.
+    fn decode(input: &mut &[u8]) -> Result<Option<Self>> {
+        Ok(match u8::decode(input)? {
+            0 => Some(Self::Immortal),
+            _ => {
+                u8::decode(input)?;
+                None
+            }
+        })
+    }
+}
+
+#[derive(Debug, Decode, Encode)]
+struct Transfer {
+    pub dest: MultiAddress,
+    #[codec(compact)]
+    pub amount: u128,
+}
+
+const TRANSFER_OP_TYPE: &str = "Balances.Transfer";
+const CALL_OP_TYPE: &str = "Call";
+const TRANSFER_PALLET: &str = "Balances";
+const TRANSFER_CALL: &str = "transfer";
+
+/// Pallet and call index of `Balances.transfer` in the runtimes of the supported networks.
+///
+/// Signed extrinsics only carry the indexes of their call, the connector resolves them from
+/// the runtime metadata of the node where one is available.
+pub fn transfer_call_index(config: &BlockchainConfig) -> (u8, u8) {
+    match config.network {
+        "kusama" => (4, 0),
+        _ => (5, 0),
+    }
+}
+
+fn encode_address(config: &BlockchainConfig, account: &AccountId32) -> String {
+    Address::from_public_key_bytes(config.address_format, &account.0)
+        .address()
+        .into()
+}
+
+/// Maps a call to operations, calls other than transfers are reported as a single operation.
+fn call_operations(
+    config: &BlockchainConfig,
+    signer: Option<&str>,
+    transfer: bool,
+    call: &[u8],
+) -> Result<Vec<Operation>> {
+    let [pallet_index, call_index, call_args @ ..] = call else {
+        bail!("invalid call");
+    };
+    let account = signer.map(|signer| AccountIdentifier::new(signer.into()));
+    if transfer {
+        let Transfer { dest, amount } = Transfer::decode(&mut &call_args[..])?;
+        let MultiAddress::Id(dest) = dest;
+        return Ok(vec![
+            Operation {
+                operation_identifier: OperationIdentifier::new(0),
+                r#type: TRANSFER_OP_TYPE.into(),
+                account,
+                amount: Some(Amount::new(format!("-{amount}"), config.currency())),
+                ..Default::default()
+            },
+            Operation {
+                operation_identifier: OperationIdentifier::new(1),
+                related_operations: Some(vec![OperationIdentifier::new(0)]),
+                r#type: TRANSFER_OP_TYPE.into(),
+                account: Some(AccountIdentifier::new(encode_address(config, &dest))),
+                amount: Some(Amount::new(amount.to_string(), config.currency())),
+                ..Default::default()
+            },
+        ]);
+    }
+    Ok(vec![Operation {
+        operation_identifier: OperationIdentifier::new(0),
+        r#type: CALL_OP_TYPE.into(),
+        account,
+        metadata: Some(json!({
+            "pallet_index": pallet_index,
+            "call_index": call_index,
+            "call_args": hex::encode(call_args),
+        })),
+        ..Default::default()
+    }])
+}
+
+/// Everything needed to sign an extrinsic and to assemble it once the signature is known.
+#[derive(Debug, Decode, Encode)]
+struct UnsignedExtrinsic {
+    address: AccountId32,
+    /// Names of the pallet and call that the indexes at the start of `call` were resolved from.
+    pallet_name: String,
+    call_name: String,
+    call: Vec<u8>,
+    #[codec(compact)]
+    nonce: u64,
+    spec_version: u32,
+    transaction_version: u32,
+    genesis_hash: [u8; 32],
+}
+
+impl UnsignedExtrinsic {
+    fn extra_parameters(&self) -> (Era, Compact<u64>, Compact<u128>) {
+        (
+            Era::Immortal,
+            Compact(self.nonce),
+            // plain tip
+            Compact(0u128),
+        )
+    }
+
+    fn additional_parameters(&self) -> (u32, u32, [u8; 32], [u8; 32]) {
+        (
+            self.spec_version,
+            self.transaction_version,
+            self.genesis_hash,
+            self.genesis_hash,
+        )
+    }
+
+    /// Payloads longer than 256 bytes are hashed with blake2_256 before signing.
+    fn signing_payload(&self) -> Vec<u8> {
+        let mut payload = self.call.clone();
+        self.extra_parameters().encode_to(&mut payload);
+        self.additional_parameters().encode_to(&mut payload);
+        if payload.len() > 256 {
+            blake2_rfc::blake2b::blake2b(32, &[], &payload)
+                .as_bytes()
+                .to_vec()
+        } else {
+            payload
+        }
+    }
+}
+
 fn parse_address(address: &Address) -> Result<AccountId32> {
     const CHECKSUM_LEN: usize = 2;
     let body_len = 32;
@@ -78,6 +216,49 @@ fn ss58hash(data: &[u8]) -> blake2_rfc::blake2b::Blake2bResult {
 #[derive(Default)]
 pub struct PolkadotTransactionBuilder;
 
+impl PolkadotTransactionBuilder {
+    /// Parses an extrinsic as submitted to the node, transfers are recognized by the pallet
+    /// and call index of `Balances.transfer` in the runtime.
+    pub fn parse_extrinsic(
+        &self,
+        config: &BlockchainConfig,
+        transaction: &[u8],
+        transfer_call_index: (u8, u8),
+    ) -> Result<ParsedTransaction> {
+        let (pallet_index, call_index) = transfer_call_index;
+        let is_transfer = |call: &[u8]| call.starts_with(&[pallet_index, call_index]);
+        let mut input = transaction;
+        let len = Compact::<u32>::decode(&mut input)?;
+        anyhow::ensure!(len.0 as usize == input.len(), "invalid extrinsic length");
+        let version = u8::decode(&mut input)?;
+        anyhow::ensure!(version & 0b0111_1111 == 4, "unsupported extrinsic version");
+        if version & 0b1000_0000 == 0 {
+            return Ok(ParsedTransaction {
+                operations: call_operations(config, None, is_transfer(input), input)?,
+                signers: vec![],
+                signed: false,
+                metadata: None,
+            });
+        }
+        let MultiAddress::Id(address) = MultiAddress::decode(&mut input)?;
+        MultiSignature::decode(&mut input)?;
+        let immortal = Era::decode(&mut input)?.is_some();
+        let nonce = Compact::<u64>::decode(&mut input)?;
+        let tip = Compact::<u128>::decode(&mut input)?;
+        let signer = encode_address(config, &address);
+        Ok(ParsedTransaction {
+            operations: call_operations(config, Some(&signer), is_transfer(input), input)?,
+            signers: vec![signer],
+            signed: true,
+            metadata: Some(json!({
+                "nonce": nonce.0,
+                "tip": tip.0.to_string(),
+                "immortal": immortal,
+            })),
+        })
+    }
+}
+
 impl TransactionBuilder for PolkadotTransactionBuilder {
     type MetadataParams = PolkadotMetadataParams;
     type Metadata = PolkadotMetadata;
@@ -85,15 +266,9 @@ impl TransactionBuilder for PolkadotTransactionBuilder {
     fn transfer(&self, address: &Address, amount: u128) -> Result<Self::MetadataParams> {
         let address: AccountId32 = parse_address(address)?;
         let dest = MultiAddress::Id(address);
-        #[derive(Debug, Decode, Encode)]
-        struct Transfer {
-            pub dest: MultiAddress,
-            #[codec(compact)]
-            pub amount: u128,
-        }
         Ok(PolkadotMetadataParams {
-            pallet_name: "Balances".into(),
-            call_name: "transfer".into(),
+            pallet_name: TRANSFER_PALLET.into(),
+            call_name: TRANSFER_CALL.into(),
             call_args: Transfer { dest, amount }.encode(),
         })
     }
@@ -108,70 +283,233 @@ impl TransactionBuilder for PolkadotTransactionBuilder {
         bail!("Not Implemented")
     }
 
-    fn create_and_sign(
+    fn create_unsigned(
         &self,
         _config: &BlockchainConfig,
         metadata_params: &Self::MetadataParams,
         metadata: &Self::Metadata,
-        secret_key: &SecretKey,
-    ) -> Vec<u8> {
-        let address = AccountId32(secret_key.public_key().to_bytes().try_into().unwrap());
-        let address = MultiAddress::Id(address);
-        let extra_parameters = (
-            Era::Immortal,
-            Compact(metadata.nonce as u64),
-            // plain tip
-            Compact(0u128),
-        );
-        let additional_parameters = (
-            metadata.spec_version,
-            metadata.transaction_version,
-            metadata.genesis_hash,
-            metadata.genesis_hash,
+        public_key: &PublicKey,
+    ) -> Result<UnsignedTransaction> {
+        let address = AccountId32(
+            public_key
+                .to_bytes()
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("invalid public key"))?,
         );
+        let mut call = vec![];
+        metadata.pallet_index.encode_to(&mut call);
+        metadata.call_index.encode_to(&mut call);
+        call.extend(&metadata_params.call_args);
+        let unsigned = UnsignedExtrinsic {
+            address,
+            pallet_name: metadata_params.pallet_name.clone(),
+            call_name: metadata_params.call_name.clone(),
+            call,
+            nonce: metadata.nonce as _,
+            spec_version: metadata.spec_version,
+            transaction_version: metadata.transaction_version,
+            genesis_hash: metadata.genesis_hash,
+        };
+        Ok(UnsignedTransaction {
+            transaction: unsigned.encode(),
+            signing_payload: unsigned.signing_payload(),
+        })
+    }
 
-        // construct payload
-        let mut payload = vec![];
-        metadata.pallet_index.encode_to(&mut payload);
-        metadata.call_index.encode_to(&mut payload);
-        payload.extend(&metadata_params.call_args);
-        extra_parameters.encode_to(&mut payload);
-        additional_parameters.encode_to(&mut payload);
-
-        // sign payload
-        let signature = if payload.len() > 256 {
-            let hash = blake2_rfc::blake2b::blake2b(64, &[], &payload);
-            secret_key.sign(hash.as_bytes(), "substrate")
-        } else {
-            secret_key.sign(&payload, "substrate")
+    fn combine(
+        &self,
+        _config: &BlockchainConfig,
+        transaction: &[u8],
+        signature: &Signature,
+    ) -> Result<Vec<u8>> {
+        let unsigned = UnsignedExtrinsic::decode(&mut &transaction[..])?;
+        let extra_parameters = unsigned.extra_parameters();
+        let signature = match signature {
+            Signature::Sr25519(signature) => MultiSignature::Sr25519(signature.to_bytes()),
+            Signature::Ed25519(signature) => MultiSignature::Ed25519(signature.to_bytes()),
+            _ => anyhow::bail!("unsupported signature type"),
         };
-        let signature =
-            MultiSignature::Sr25519(signature.to_bytes().as_slice().try_into().unwrap());
 
         // encode transaction
         let mut encoded = vec![];
         // "is signed" + transaction protocol version (4)
         (0b10000000 + 4u8).encode_to(&mut encoded);
         // from address for signature
-        address.encode_to(&mut encoded);
+        MultiAddress::Id(unsigned.address).encode_to(&mut encoded);
         // signature encode pending to vector
         signature.encode_to(&mut encoded);
         // attach custom extra params
         extra_parameters.encode_to(&mut encoded);
         // and now, call data
-        metadata.pallet_index.encode_to(&mut encoded);
-        metadata.call_index.encode_to(&mut encoded);
-        encoded.extend(&metadata_params.call_args);
+        encoded.extend(&unsigned.call);
 
         // now, prefix byte length:
         let len = Compact(encoded.len() as u32);
         let mut transaction = vec![];
         len.encode_to(&mut transaction);
         transaction.extend(encoded);
-        transaction
+        Ok(transaction)
+    }
+
+    fn create_and_sign(
+        &self,
+        config: &BlockchainConfig,
+        metadata_params: &Self::MetadataParams,
+        metadata: &Self::Metadata,
+        secret_key: &SecretKey,
+    ) -> Result<Vec<u8>> {
+        let unsigned =
+            self.create_unsigned(config, metadata_params, metadata, &secret_key.public_key())?;
+        let signature = secret_key.sign(&unsigned.signing_payload, "substrate");
+        self.combine(config, &unsigned.transaction, &signature)
+    }
+
+    fn parse(
+        &self,
+        config: &BlockchainConfig,
+        transaction: &[u8],
+        signed: bool,
+    ) -> Result<ParsedTransaction> {
+        if !signed {
+            let unsigned = UnsignedExtrinsic::decode(&mut &transaction[..])?;
+            let signer = encode_address(config, &unsigned.address);
+            return Ok(ParsedTransaction {
+                operations: call_operations(
+                    config,
+                    Some(&signer),
+                    unsigned.pallet_name == TRANSFER_PALLET && unsigned.call_name == TRANSFER_CALL,
+                    &unsigned.call,
+                )?,
+                signers: vec![],
+                signed: false,
+                metadata: Some(json!({
+                    "nonce": unsigned.nonce,
+                    "spec_version": unsigned.spec_version,
+                    "transaction_version": unsigned.transaction_version,
+                    "genesis_hash": hex::encode(unsigned.genesis_hash),
+                })),
+            });
+        }
+        self.parse_extrinsic(config, transaction, transfer_call_index(config))
+    }
+
+    /// Matches the extrinsic hash reported by the node, which includes the length prefix.
+    fn hash(&self, _config: &BlockchainConfig, transaction: &[u8]) -> Result<Vec<u8>> {
+        let mut input = transaction;
+        let len = Compact::<u32>::decode(&mut input)?;
+        anyhow::ensure!(len.0 as usize == input.len(), "invalid extrinsic length");
+        Ok(blake2_rfc::blake2b::blake2b(32, &[], transaction)
+            .as_bytes()
+            .to_vec())
     }
 
     fn deploy_contract(&self, _contract_binary: Vec<u8>) -> Result<Self::MetadataParams> {
         bail!("Not Implemented")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rosetta_core::crypto::Algorithm;
+
+    #[test]
+    fn test_detached_signing() -> Result<()> {
+        let config = rosetta_config_polkadot::config("dev")?;
+        let secret_key = SecretKey::from_bytes(Algorithm::Sr25519, &[1; 32])?;
+        let public_key = secret_key.public_key();
+        let builder = PolkadotTransactionBuilder;
+        let metadata_params =
+            builder.transfer(&public_key.to_address(config.address_format), 1000)?;
+        let metadata = PolkadotMetadata {
+            nonce: 1,
+            spec_version: 1,
+            transaction_version: 1,
+            genesis_hash: [2; 32],
+            pallet_index: 5,
+            call_index: 0,
+            call_hash: [0; 32],
+        };
+
+        let unsigned =
+            builder.create_unsigned(&config, &metadata_params, &metadata, &public_key)?;
+        let signature = secret_key.sign(&unsigned.signing_payload, "substrate");
+        let signed = builder.combine(&config, &unsigned.transaction, &signature)?;
+
+        let mut input = &signed[..];
+        let len = Compact::<u32>::decode(&mut input)?;
+        assert_eq!(len.0 as usize, input.len());
+        assert_eq!(input[0], 0x84);
+        assert_eq!(&input[2..34], &public_key.to_bytes()[..]);
+        assert_eq!(&input[35..99], &signature.to_bytes()[..]);
+        assert!(input.ends_with(&metadata_params.call_args));
+
+        let mut long_params = metadata_params.clone();
+        long_params.call_args = vec![0; 300];
+        let long = builder.create_unsigned(&config, &long_params, &metadata, &public_key)?;
+        assert_eq!(long.signing_payload.len(), 32);
+
+        let hash = builder.hash(&config, &signed)?;
+        assert_eq!(hash.len(), 32);
+        assert!(builder.hash(&config, &signed[..signed.len() - 1]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let config = rosetta_config_polkadot::config("dev")?;
+        let secret_key = SecretKey::from_bytes(Algorithm::Sr25519, &[1; 32])?;
+        let from = secret_key.public_key().to_address(config.address_format);
+        let to = SecretKey::from_bytes(Algorithm::Sr25519, &[2; 32])?
+            .public_key()
+            .to_address(config.address_format);
+        let builder = PolkadotTransactionBuilder;
+        let metadata_params = builder.transfer(&to, 1000)?;
+        let metadata = PolkadotMetadata {
+            nonce: 1,
+            spec_version: 1,
+            transaction_version: 1,
+            genesis_hash: [2; 32],
+            pallet_index: transfer_call_index(&config).0,
+            call_index: transfer_call_index(&config).1,
+            call_hash: [0; 32],
+        };
+        let unsigned = builder.create_unsigned(
+            &config,
+            &metadata_params,
+            &metadata,
+            &secret_key.public_key(),
+        )?;
+        let signed = builder.create_and_sign(&config, &metadata_params, &metadata, &secret_key)?;
+
+        let parsed = builder.parse(&config, &unsigned.transaction, false)?;
+        assert!(!parsed.signed);
+        assert!(parsed.signers.is_empty());
+        let ops = &parsed.operations;
+        assert_eq!(ops.len(), 2);
+        assert_eq!(ops[0].r#type, TRANSFER_OP_TYPE);
+        assert_eq!(ops[0].account.as_ref().unwrap().address, from.address());
+        assert_eq!(ops[0].amount.as_ref().unwrap().value, "-1000");
+        assert_eq!(ops[1].account.as_ref().unwrap().address, to.address());
+        assert_eq!(ops[1].amount.as_ref().unwrap().value, "1000");
+
+        let parsed_signed = builder.parse(&config, &signed, true)?;
+        assert!(parsed_signed.signed);
+        assert_eq!(parsed_signed.signers, vec![from.address().to_string()]);
+        assert_eq!(parsed_signed.operations, parsed.operations);
+
+        // runtimes with other indexes report the call by index
+        let parsed_other = builder.parse_extrinsic(&config, &signed, (9, 0))?;
+        let ops = &parsed_other.operations;
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].r#type, CALL_OP_TYPE);
+        assert_eq!(ops[0].account.as_ref().unwrap().address, from.address());
+        let metadata = ops[0].metadata.as_ref().unwrap();
+        assert_eq!(metadata["pallet_index"], transfer_call_index(&config).0);
+        assert_eq!(
+            metadata["call_args"],
+            hex::encode(&metadata_params.call_args)
+        );
+        Ok(())
+    }
+}
@@ -105,6 +105,8 @@ pub struct SearchOpts {
     pub offset: Option<i64>,
     #[clap(long)]
     pub limit: Option<i64>,
+    #[clap(long)]
+    pub cursor: Option<String>,
     #[clap(flatten)]
     pub transaction: TransactionIdentifierOpts,
     #[clap(flatten)]
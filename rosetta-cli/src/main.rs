@@ -163,6 +163,7 @@ async fn main() -> Result<()> {
                 currency: opts.currency.currency_identifier(),
                 address: opts.address,
                 status: opts.status,
+                cursor: opts.cursor,
             };
             let res = client.search_transactions(&req).await?;
             println!("{res:#?}");
@@ -1,15 +1,16 @@
 use crate::types::{
-    AccountBalanceRequest, AccountBalanceResponse, AccountCoinsRequest, AccountCoinsResponse,
-    AccountFaucetRequest, BlockRequest, BlockResponse, BlockTransactionRequest,
-    BlockTransactionResponse, ConstructionCombineRequest, ConstructionCombineResponse,
-    ConstructionDeriveRequest, ConstructionDeriveResponse, ConstructionHashRequest,
-    ConstructionMetadataRequest, ConstructionMetadataResponse, ConstructionParseRequest,
-    ConstructionParseResponse, ConstructionPayloadsRequest, ConstructionPayloadsResponse,
-    ConstructionPreprocessRequest, ConstructionPreprocessResponse, ConstructionSubmitRequest,
-    EventsBlocksRequest, EventsBlocksResponse, MempoolResponse, MempoolTransactionRequest,
-    MempoolTransactionResponse, MetadataRequest, NetworkIdentifier, NetworkListResponse,
-    NetworkOptionsResponse, NetworkRequest, NetworkStatusResponse, SearchTransactionsRequest,
-    SearchTransactionsResponse, TransactionIdentifierResponse,
+    AccountBalanceHistoryRequest, AccountBalanceHistoryResponse, AccountBalanceRequest,
+    AccountBalanceResponse, AccountCoinsRequest, AccountCoinsResponse, AccountFaucetRequest,
+    BlockRequest, BlockResponse, BlockTransactionRequest, BlockTransactionResponse,
+    ConstructionCombineRequest, ConstructionCombineResponse, ConstructionDeriveRequest,
+    ConstructionDeriveResponse, ConstructionHashRequest, ConstructionMetadataRequest,
+    ConstructionMetadataResponse, ConstructionParseRequest, ConstructionParseResponse,
+    ConstructionPayloadsRequest, ConstructionPayloadsResponse, ConstructionPreprocessRequest,
+    ConstructionPreprocessResponse, ConstructionSubmitRequest, EventsBlocksRequest,
+    EventsBlocksResponse, MempoolResponse, MempoolTransactionRequest, MempoolTransactionResponse,
+    MetadataRequest, NetworkIdentifier, NetworkListResponse, NetworkOptionsResponse,
+    NetworkRequest, NetworkStatusResponse, SearchTransactionsRequest, SearchTransactionsResponse,
+    TransactionIdentifierResponse,
 };
 use anyhow::Result;
 use rosetta_core::types::{CallRequest, CallResponse};
@@ -91,6 +92,14 @@ impl Client {
         self.post("/account/balance", &request).await
     }
 
+    /// Make a call to the /account/balance/history endpoint.
+    pub async fn account_balance_history(
+        &self,
+        request: &AccountBalanceHistoryRequest,
+    ) -> Result<AccountBalanceHistoryResponse> {
+        self.post("/account/balance/history", &request).await
+    }
+
     /// Make a call to the /account/coins endpoint.
     pub async fn account_coins(
         &self,
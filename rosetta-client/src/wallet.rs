@@ -72,16 +72,16 @@ impl GenericTransactionBuilder {
         metadata_params: serde_json::Value,
         metadata: serde_json::Value,
         secret_key: &SecretKey,
-    ) -> Vec<u8> {
+    ) -> Result<Vec<u8>> {
         match self {
             Self::Ethereum(tx) => {
-                let metadata_params = serde_json::from_value(metadata_params).unwrap();
-                let metadata = serde_json::from_value(metadata).unwrap();
+                let metadata_params = serde_json::from_value(metadata_params)?;
+                let metadata = serde_json::from_value(metadata)?;
                 tx.create_and_sign(config, &metadata_params, &metadata, secret_key)
             }
             Self::Polkadot(tx) => {
-                let metadata_params = serde_json::from_value(metadata_params).unwrap();
-                let metadata = serde_json::from_value(metadata).unwrap();
+                let metadata_params = serde_json::from_value(metadata_params)?;
+                let metadata = serde_json::from_value(metadata)?;
                 tx.create_and_sign(config, &metadata_params, &metadata, secret_key)
             }
         }
@@ -251,7 +251,7 @@ impl Wallet {
             metadata_params,
             metadata,
             self.secret_key.secret_key(),
-        );
+        )?;
         self.submit(&transaction).await
     }
 
@@ -300,6 +300,7 @@ impl Wallet {
             r#type: None,
             address: None,
             success: None,
+            cursor: None,
         };
         let resp = self.client.search_transactions(&req).await?;
         anyhow::ensure!(resp.transactions.len() == 1);
@@ -322,6 +323,7 @@ impl Wallet {
             r#type: None,
             address: None,
             success: None,
+            cursor: None,
         };
         TransactionStream::new(self.client.clone(), req)
     }
@@ -437,7 +439,7 @@ impl EthereumExt for Wallet {
     }
 }
 
-/// A paged transaction stream.
+/// A paged transaction stream, newest transactions first.
 pub struct TransactionStream {
     client: Client,
     request: SearchTransactionsRequest,
@@ -447,8 +449,7 @@ pub struct TransactionStream {
 }
 
 impl TransactionStream {
-    fn new(client: Client, mut request: SearchTransactionsRequest) -> Self {
-        request.offset = Some(0);
+    fn new(client: Client, request: SearchTransactionsRequest) -> Self {
         Self {
             client,
             request,
@@ -477,9 +478,10 @@ impl Stream for TransactionStream {
                     Poll::Pending => return Poll::Pending,
                     Poll::Ready(Ok(response)) => {
                         self.future.take();
-                        self.request.offset = response.next_offset;
                         self.total_count = Some(response.total_count);
-                        if response.transactions.len() < self.request.limit.unwrap() as _ {
+                        // cursors stay valid while new blocks are indexed, unlike offsets
+                        self.request.cursor = response.next_cursor;
+                        if self.request.cursor.is_none() {
                             self.finished = true;
                         }
                         if response.transactions.is_empty() {
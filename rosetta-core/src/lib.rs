@@ -1,11 +1,13 @@
 use crate::crypto::address::{Address, AddressFormat};
-use crate::crypto::{Algorithm, PublicKey, SecretKey};
+use crate::crypto::{Algorithm, PublicKey, SecretKey, Signature};
 use crate::types::{
-    Block, BlockIdentifier, CallRequest, Coin, Currency, CurveType, NetworkIdentifier,
-    PartialBlockIdentifier, SignatureType, Transaction, TransactionIdentifier,
+    BalanceExemption, Block, BlockIdentifier, CallRequest, Coin, Currency, CurveType,
+    NetworkIdentifier, Operation, OperationStatus, PartialBlockIdentifier, Peer, SignatureType,
+    Transaction, TransactionIdentifier,
 };
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::stream::BoxStream;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::Value;
@@ -16,6 +18,9 @@ pub use rosetta_types as types;
 
 type NodeCommand = Arc<dyn Fn(&str, u16) -> Vec<String> + Send + Sync + 'static>;
 
+/// Stream of new chain heads, ends when the subscription is dropped by the node.
+pub type BlockStream = BoxStream<'static, BlockIdentifier>;
+
 #[derive(Clone)]
 pub struct BlockchainConfig {
     pub blockchain: &'static str,
@@ -25,6 +30,7 @@ pub struct BlockchainConfig {
     pub coin: u32,
     pub bip44: bool,
     pub utxo: bool,
+    pub historical_balance_lookup: bool,
     pub currency_unit: &'static str,
     pub currency_symbol: &'static str,
     pub currency_decimals: u32,
@@ -64,13 +70,29 @@ impl BlockchainConfig {
 
 #[async_trait]
 pub trait BlockchainClient: Sized + Send + Sync + 'static {
-    type MetadataParams: DeserializeOwned + Send + Sync + 'static;
-    type Metadata: Serialize;
+    type MetadataParams: DeserializeOwned + Serialize + Send + Sync + 'static;
+    type Metadata: Serialize + DeserializeOwned;
+    type TransactionBuilder: TransactionBuilder<
+        MetadataParams = Self::MetadataParams,
+        Metadata = Self::Metadata,
+    >;
+    fn create_config(network: &str) -> Result<BlockchainConfig>;
     async fn new(network: &str, addr: &str) -> Result<Self>;
     fn config(&self) -> &BlockchainConfig;
     fn genesis_block(&self) -> &BlockIdentifier;
+    fn operation_statuses(&self) -> Vec<OperationStatus>;
+    fn operation_types(&self) -> Vec<String>;
+    fn call_methods(&self) -> Vec<String>;
+    fn balance_exemptions(&self) -> Vec<BalanceExemption>;
     async fn node_version(&self) -> Result<String>;
     async fn current_block(&self) -> Result<BlockIdentifier>;
+    /// Latest block that can no longer be reverted by a reorg.
+    async fn finalized_block(&self) -> Result<BlockIdentifier>;
+    async fn block_identifier(&self, block: &PartialBlockIdentifier) -> Result<BlockIdentifier>;
+    /// Unix timestamp of the block in milliseconds.
+    async fn block_timestamp(&self, block: &BlockIdentifier) -> Result<i64>;
+    async fn peers(&self) -> Result<Vec<Peer>>;
+    async fn syncing(&self) -> Result<bool>;
     async fn balance(&self, address: &Address, block: &BlockIdentifier) -> Result<u128>;
     async fn coins(&self, address: &Address, block: &BlockIdentifier) -> Result<Vec<Coin>>;
     async fn faucet(&self, address: &Address, param: u128) -> Result<Vec<u8>>;
@@ -86,7 +108,13 @@ pub trait BlockchainClient: Sized + Send + Sync + 'static {
         block: &BlockIdentifier,
         tx: &TransactionIdentifier,
     ) -> Result<Transaction>;
+    async fn mempool(&self) -> Result<Vec<TransactionIdentifier>>;
+    async fn mempool_transaction(&self, tx: &TransactionIdentifier) -> Result<Transaction>;
     async fn call(&self, req: &CallRequest) -> Result<Value>;
+    /// Subscribes to new heads, returns `None` when the node can only be polled.
+    async fn listen(&self) -> Result<Option<BlockStream>> {
+        Ok(None)
+    }
 }
 
 pub trait RosettaAlgorithm {
@@ -132,11 +160,52 @@ pub trait TransactionBuilder: Default + Sized {
 
     fn deploy_contract(&self, contract_binary: Vec<u8>) -> Result<Self::MetadataParams>;
 
+    fn create_unsigned(
+        &self,
+        config: &BlockchainConfig,
+        metadata_params: &Self::MetadataParams,
+        metadata: &Self::Metadata,
+        public_key: &PublicKey,
+    ) -> Result<UnsignedTransaction>;
+
+    fn combine(
+        &self,
+        config: &BlockchainConfig,
+        transaction: &[u8],
+        signature: &Signature,
+    ) -> Result<Vec<u8>>;
+
     fn create_and_sign(
         &self,
         config: &BlockchainConfig,
         metadata_params: &Self::MetadataParams,
         metdata: &Self::Metadata,
         secret_key: &SecretKey,
-    ) -> Vec<u8>;
+    ) -> Result<Vec<u8>>;
+
+    fn parse(
+        &self,
+        config: &BlockchainConfig,
+        transaction: &[u8],
+        signed: bool,
+    ) -> Result<ParsedTransaction>;
+
+    fn hash(&self, config: &BlockchainConfig, transaction: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// An encoded transaction without a signature and the payload that needs to be signed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnsignedTransaction {
+    pub transaction: Vec<u8>,
+    pub signing_payload: Vec<u8>,
+}
+
+/// A transaction decoded into operations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedTransaction {
+    pub operations: Vec<Operation>,
+    /// Addresses of the signers, empty for unsigned transactions.
+    pub signers: Vec<String>,
+    pub signed: bool,
+    pub metadata: Option<Value>,
 }
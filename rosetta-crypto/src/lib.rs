@@ -301,10 +301,13 @@ impl Signature {
             Algorithm::EcdsaSecp256k1 => {
                 Signature::EcdsaSecp256k1(ecdsa::Signature::try_from(bytes)?)
             }
-            Algorithm::EcdsaRecoverableSecp256k1 => Signature::EcdsaRecoverableSecp256k1(
-                ecdsa::Signature::try_from(&bytes[..64])?,
-                RecoveryId::from_byte(bytes[64]).context("invalid signature")?,
-            ),
+            Algorithm::EcdsaRecoverableSecp256k1 => {
+                anyhow::ensure!(bytes.len() == 65, "invalid signature length");
+                Signature::EcdsaRecoverableSecp256k1(
+                    ecdsa::Signature::try_from(&bytes[..64])?,
+                    RecoveryId::from_byte(bytes[64]).context("invalid signature")?,
+                )
+            }
             Algorithm::EcdsaSecp256r1 => {
                 Signature::EcdsaSecp256r1(ecdsa::Signature::try_from(bytes)?)
             }
@@ -0,0 +1,421 @@
+use serde_json::json;
+use std::io::ErrorKind;
+use tide::{Body, Response};
+
+/// Errors returned by the server.
+///
+/// Codes are stable and the full catalog is published through `/network/options`, so
+/// clients can tell transient node failures apart from invalid requests.
+#[derive(Debug)]
+pub enum Error {
+    Unimplemented,
+    Unsupported,
+    UnsupportedNetwork,
+    UnsupportedOption,
+    MissingPublicKey,
+    UnsupportedCurveType,
+    MoreThanOneSignature,
+    InvalidSignatureType,
+    HistoricalBalanceLookupUnsupported,
+    InvalidTransaction(anyhow::Error),
+    RpcError(anyhow::Error),
+    InvalidRequest(anyhow::Error),
+    InvalidHex(anyhow::Error),
+    InvalidPublicKey(anyhow::Error),
+    InvalidSignature(anyhow::Error),
+    BlockNotFound(anyhow::Error),
+    TransactionNotFound(anyhow::Error),
+    NodeUnavailable(anyhow::Error),
+    Internal(anyhow::Error),
+    Offline,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.error() {
+            Some(error) => write!(f, "{}: {error}", self.message()),
+            None => f.write_str(self.message()),
+        }
+    }
+}
+
+impl From<tide::Error> for Error {
+    fn from(error: tide::Error) -> Self {
+        let status = error.status();
+        let error = error.into_inner();
+        if error.is::<hex::FromHexError>() {
+            Self::InvalidHex(error)
+        } else if error.is::<serde_json::Error>() || status.is_client_error() {
+            Self::InvalidRequest(error)
+        } else {
+            Self::Internal(error)
+        }
+    }
+}
+
+impl Error {
+    /// Classifies an error returned by the node client.
+    pub fn rpc(error: anyhow::Error) -> Self {
+        let unavailable = error.chain().any(|cause| {
+            if let Some(error) = cause.downcast_ref::<std::io::Error>() {
+                return matches!(
+                    error.kind(),
+                    ErrorKind::TimedOut
+                        | ErrorKind::ConnectionRefused
+                        | ErrorKind::ConnectionReset
+                        | ErrorKind::ConnectionAborted
+                        | ErrorKind::NotConnected
+                        | ErrorKind::BrokenPipe
+                );
+            }
+            let msg = cause.to_string().to_lowercase();
+            msg.contains("timed out")
+                || msg.contains("timeout")
+                || msg.contains("connection refused")
+        });
+        if unavailable {
+            return Self::NodeUnavailable(error);
+        }
+        let msg = error.to_string().to_lowercase();
+        if msg.contains("block not found") {
+            Self::BlockNotFound(error)
+        } else if msg.contains("transaction not found") {
+            Self::TransactionNotFound(error)
+        } else {
+            Self::RpcError(error)
+        }
+    }
+
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::Unimplemented => 1,
+            Self::Unsupported => 2,
+            Self::UnsupportedNetwork => 3,
+            Self::UnsupportedOption => 4,
+            Self::MissingPublicKey => 5,
+            Self::UnsupportedCurveType => 6,
+            Self::MoreThanOneSignature => 7,
+            Self::InvalidSignatureType => 8,
+            Self::HistoricalBalanceLookupUnsupported => 9,
+            Self::InvalidTransaction(_) => 10,
+            Self::RpcError(_) => 11,
+            Self::InvalidRequest(_) => 12,
+            Self::InvalidHex(_) => 13,
+            Self::InvalidPublicKey(_) => 14,
+            Self::InvalidSignature(_) => 15,
+            Self::BlockNotFound(_) => 16,
+            Self::TransactionNotFound(_) => 17,
+            Self::NodeUnavailable(_) => 18,
+            Self::Internal(_) => 19,
+            Self::Offline => 20,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::Unimplemented => "unimplemented",
+            Self::Unsupported => "unsupported",
+            Self::UnsupportedNetwork => "unsupported network",
+            Self::UnsupportedOption => "unsupported option",
+            Self::MissingPublicKey => "missing public key",
+            Self::UnsupportedCurveType => "unsupported curve type",
+            Self::MoreThanOneSignature => "expected one signature",
+            Self::InvalidSignatureType => "invalid signature type",
+            Self::HistoricalBalanceLookupUnsupported => "historical balance lookup unsupported",
+            Self::InvalidTransaction(_) => "invalid transaction",
+            Self::RpcError(_) => "rpc error",
+            Self::InvalidRequest(_) => "invalid request",
+            Self::InvalidHex(_) => "invalid hex",
+            Self::InvalidPublicKey(_) => "invalid public key",
+            Self::InvalidSignature(_) => "invalid signature",
+            Self::BlockNotFound(_) => "block not found",
+            Self::TransactionNotFound(_) => "transaction not found",
+            Self::NodeUnavailable(_) => "node unavailable",
+            Self::Internal(_) => "internal error",
+            Self::Offline => "endpoint unavailable in offline mode",
+        }
+    }
+
+    /// Whether the same request may succeed when retried later.
+    pub fn retriable(&self) -> bool {
+        matches!(
+            self,
+            Self::BlockNotFound(_) | Self::TransactionNotFound(_) | Self::NodeUnavailable(_)
+        )
+    }
+
+    /// All errors the server can return, published through `/network/options`.
+    pub fn catalog() -> Vec<rosetta_core::types::Error> {
+        let error = || anyhow::anyhow!("");
+        [
+            Self::Unimplemented,
+            Self::Unsupported,
+            Self::UnsupportedNetwork,
+            Self::UnsupportedOption,
+            Self::MissingPublicKey,
+            Self::UnsupportedCurveType,
+            Self::MoreThanOneSignature,
+            Self::InvalidSignatureType,
+            Self::HistoricalBalanceLookupUnsupported,
+            Self::InvalidTransaction(error()),
+            Self::RpcError(error()),
+            Self::InvalidRequest(error()),
+            Self::InvalidHex(error()),
+            Self::InvalidPublicKey(error()),
+            Self::InvalidSignature(error()),
+            Self::BlockNotFound(error()),
+            Self::TransactionNotFound(error()),
+            Self::NodeUnavailable(error()),
+            Self::Internal(error()),
+            Self::Offline,
+        ]
+        .iter()
+        .map(|error| rosetta_core::types::Error {
+            code: error.code(),
+            message: error.message().into(),
+            description: None,
+            retriable: error.retriable(),
+            details: None,
+        })
+        .collect()
+    }
+
+    pub fn error(&self) -> Option<&anyhow::Error> {
+        let error = match self {
+            Self::InvalidTransaction(error)
+            | Self::RpcError(error)
+            | Self::InvalidRequest(error)
+            | Self::InvalidHex(error)
+            | Self::InvalidPublicKey(error)
+            | Self::InvalidSignature(error)
+            | Self::BlockNotFound(error)
+            | Self::TransactionNotFound(error)
+            | Self::NodeUnavailable(error)
+            | Self::Internal(error) => error,
+            _ => return None,
+        };
+        Some(error)
+    }
+
+    pub fn description(&self) -> Option<String> {
+        self.error().map(|error| error.to_string())
+    }
+
+    /// The chain of underlying causes, outermost first.
+    pub fn details(&self) -> Option<serde_json::Value> {
+        let causes = self
+            .error()?
+            .chain()
+            .map(|cause| cause.to_string())
+            .collect::<Vec<_>>();
+        Some(json!({ "causes": causes }))
+    }
+
+    pub fn to_error(&self) -> rosetta_core::types::Error {
+        rosetta_core::types::Error {
+            code: self.code(),
+            message: self.message().into(),
+            description: self.description(),
+            retriable: self.retriable(),
+            details: self.details(),
+        }
+    }
+
+    pub fn to_response(&self) -> Response {
+        Response::builder(500)
+            .body(Body::from_json(&self.to_error()).unwrap())
+            .build()
+    }
+
+    pub fn to_result(&self) -> tide::Result {
+        Ok(self.to_response())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::tests::{mock_indexer, MockClient};
+    use crate::{BlockchainClient, State};
+    use anyhow::Result;
+    use rosetta_core::types::{
+        AccountBalanceHistoryRequest, AccountIdentifier, Amount, ConstructionHashRequest,
+        ConstructionPayloadsRequest, ConstructionPreprocessRequest, ConstructionPreprocessResponse,
+        NetworkOptionsResponse, NetworkRequest, Operation, OperationIdentifier,
+        SearchTransactionsRequest,
+    };
+    use tide::http::{Method, Request, Url};
+
+    async fn respond(
+        state: State<MockClient>,
+        path: &str,
+        body: Body,
+    ) -> Result<tide::http::Response> {
+        let app = crate::server(state);
+        let mut req = Request::new(Method::Post, Url::parse("http://localhost/")?.join(path)?);
+        req.set_body(body);
+        app.respond(req).await.map_err(|err| err.into_inner())
+    }
+
+    async fn post(path: &str, body: Body) -> Result<rosetta_core::types::Error> {
+        let (_, indexer) = mock_indexer()?;
+        let mut res = respond(State::online(indexer), path, body).await?;
+        assert_eq!(res.status(), 500);
+        res.body_json().await.map_err(|err| err.into_inner())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_request() -> Result<()> {
+        let error = post("network/status", Body::from_string("{".into())).await?;
+        assert_eq!(
+            error.code,
+            Error::InvalidRequest(anyhow::anyhow!("")).code()
+        );
+        assert!(!error.retriable);
+
+        let config = MockClient::create_config("dev")?;
+        let mut req = AccountBalanceHistoryRequest::new(
+            config.network(),
+            AccountIdentifier::new("alice".into()),
+        );
+        req.limit = Some(-1);
+        let error = post("account/balance/history", Body::from_json(&req).unwrap()).await?;
+        assert_eq!(
+            error.code,
+            Error::InvalidRequest(anyhow::anyhow!("")).code()
+        );
+
+        let mut req = SearchTransactionsRequest::new(config.network());
+        req.offset = Some(-1);
+        let error = post("search/transactions", Body::from_json(&req).unwrap()).await?;
+        assert_eq!(
+            error.code,
+            Error::InvalidRequest(anyhow::anyhow!("")).code()
+        );
+        req.offset = Some(1);
+        req.cursor = Some("000000000000000100000000".into());
+        let error = post("search/transactions", Body::from_json(&req).unwrap()).await?;
+        assert_eq!(
+            error.code,
+            Error::InvalidRequest(anyhow::anyhow!("")).code()
+        );
+        req.offset = None;
+        req.cursor = Some("zz".into());
+        let error = post("search/transactions", Body::from_json(&req).unwrap()).await?;
+        assert_eq!(
+            error.code,
+            Error::InvalidRequest(anyhow::anyhow!("")).code()
+        );
+        assert!(!error.retriable);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_hex() -> Result<()> {
+        let (client, _) = mock_indexer()?;
+        let req = ConstructionHashRequest {
+            network_identifier: client.config().network(),
+            signed_transaction: "zz".into(),
+        };
+        let error = post("construction/hash", Body::from_json(&req).unwrap()).await?;
+        assert_eq!(error.code, Error::InvalidHex(anyhow::anyhow!("")).code());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_construction_intent() -> Result<()> {
+        let config = MockClient::create_config("dev")?;
+        let operation = |index, address: &str, value: &str| Operation {
+            operation_identifier: OperationIdentifier::new(index),
+            r#type: "TRANSFER".into(),
+            account: Some(AccountIdentifier::new(address.into())),
+            amount: Some(Amount::new(value.into(), config.currency())),
+            ..Default::default()
+        };
+        let operations = vec![operation(0, "alice", "-10"), operation(1, "bob", "10")];
+
+        let req = ConstructionPreprocessRequest::new(config.network(), operations.clone());
+        let state = State::offline(config.clone());
+        let mut res = respond(
+            state,
+            "construction/preprocess",
+            Body::from_json(&req).unwrap(),
+        )
+        .await?;
+        assert_eq!(res.status(), 200);
+        let res: ConstructionPreprocessResponse =
+            res.body_json().await.map_err(|err| err.into_inner())?;
+        assert_eq!(
+            res.required_public_keys,
+            Some(vec![AccountIdentifier::new("alice".into())])
+        );
+
+        let req = ConstructionPayloadsRequest::new(config.network(), operations[..1].to_vec());
+        let error = post("construction/payloads", Body::from_json(&req).unwrap()).await?;
+        assert_eq!(
+            error.code,
+            Error::InvalidRequest(anyhow::anyhow!("")).code()
+        );
+        let req = ConstructionPreprocessRequest::new(
+            config.network(),
+            vec![operation(0, "alice", "-9"), operation(1, "bob", "10")],
+        );
+        let error = post("construction/preprocess", Body::from_json(&req).unwrap()).await?;
+        assert_eq!(
+            error.code,
+            Error::InvalidRequest(anyhow::anyhow!("")).code()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_offline() -> Result<()> {
+        let config = MockClient::create_config("dev")?;
+        let network = NetworkRequest {
+            network_identifier: config.network(),
+            metadata: None,
+        };
+
+        let state = State::offline(config.clone());
+        let mut res = respond(
+            state.clone(),
+            "network/options",
+            Body::from_json(&network).unwrap(),
+        )
+        .await?;
+        assert_eq!(res.status(), 200);
+        let options: NetworkOptionsResponse =
+            res.body_json().await.map_err(|err| err.into_inner())?;
+        assert_eq!(options.version.node_version, "");
+        assert!(options.version.middleware_version.is_some());
+
+        let mut res = respond(state, "network/status", Body::from_json(&network).unwrap()).await?;
+        assert_eq!(res.status(), 500);
+        let error: rosetta_core::types::Error =
+            res.body_json().await.map_err(|err| err.into_inner())?;
+        assert_eq!(error.code, Error::Offline.code());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rpc_errors() {
+        let error = Error::rpc(anyhow::anyhow!("block not found"));
+        assert!(matches!(error, Error::BlockNotFound(_)));
+        assert!(error.retriable());
+        let error = Error::rpc(std::io::Error::from(ErrorKind::ConnectionRefused).into());
+        assert!(matches!(error, Error::NodeUnavailable(_)));
+        assert!(error.retriable());
+        let error = Error::rpc(anyhow::anyhow!("execution reverted"));
+        assert!(matches!(error, Error::RpcError(_)));
+        assert!(!error.retriable());
+    }
+
+    #[test]
+    fn test_catalog_codes_are_unique() {
+        let catalog = Error::catalog();
+        let mut codes = catalog.iter().map(|error| error.code).collect::<Vec<_>>();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), catalog.len());
+    }
+}
@@ -1,14 +1,25 @@
+use crate::crypto::address::Address;
+use crate::storage::{Batch, Entry, Storage, Table, Tree};
 use crate::types::{
-    AccountIdentifier, Block, BlockTransaction, CoinIdentifier, Currency, Operator,
-    PartialBlockIdentifier, SearchTransactionsRequest, SearchTransactionsResponse, Transaction,
-    TransactionIdentifier,
+    AccountBalanceHistoryRequest, AccountBalanceHistoryResponse, AccountIdentifier, Amount,
+    BalanceChange, Block, BlockEvent, BlockEventType, BlockIdentifier, BlockTransaction,
+    CoinIdentifier, Currency, EventsBlocksRequest, EventsBlocksResponse, Operation,
+    OperationStatus, Operator, PartialBlockIdentifier, SearchTransactionsRequest,
+    SearchTransactionsResponse, SyncStatus, Transaction, TransactionIdentifier,
 };
 use crate::BlockchainClient;
 use anyhow::Result;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::iter::Peekable;
 use std::ops::Deref;
-use std::path::Path;
+use std::sync::{Arc, Mutex};
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct TransactionRef {
     pub block_index: u64,
     pub transaction_index: u32,
@@ -42,11 +53,11 @@ impl TransactionRef {
 
 #[derive(Clone, Debug)]
 pub struct TransactionTable {
-    tree: sled::Tree,
+    tree: Tree,
 }
 
 impl TransactionTable {
-    pub fn new(tree: sled::Tree) -> Self {
+    pub fn new(tree: Tree) -> Self {
         Self { tree }
     }
 
@@ -58,16 +69,8 @@ impl TransactionTable {
         })
     }
 
-    pub fn set_height(&self, height: u64) -> Result<()> {
-        self.tree.insert([], &height.to_be_bytes())?;
-        Ok(())
-    }
-
-    pub fn iter(&self) -> impl Iterator<Item = Result<TransactionRef>> {
-        self.tree
-            .iter()
-            .values()
-            .map(|res| Ok(TransactionRef::from_bytes(&res?)))
+    pub fn set_height(&self, batch: &mut Batch, height: u64) {
+        self.tree.insert(batch, b"", height.to_be_bytes());
     }
 
     pub fn get(&self, tx: &TransactionIdentifier) -> Result<Option<TransactionRef>> {
@@ -77,58 +80,70 @@ impl TransactionTable {
             .map(|value| TransactionRef::from_bytes(&value)))
     }
 
-    pub fn insert(&self, tx: &TransactionIdentifier, tx_ref: &TransactionRef) -> Result<()> {
+    pub fn insert(
+        &self,
+        batch: &mut Batch,
+        tx: &TransactionIdentifier,
+        tx_ref: &TransactionRef,
+    ) -> Result<()> {
         self.tree
-            .insert(hex::decode(&tx.hash)?, &tx_ref.to_bytes()[..])?;
+            .insert(batch, hex::decode(&tx.hash)?, tx_ref.to_bytes());
         Ok(())
     }
 
-    pub fn len(&self) -> usize {
-        self.tree.len()
-    }
-
-    #[allow(unused)]
-    pub fn remove(&self, tx: &TransactionIdentifier) -> Result<()> {
-        self.tree.remove(hex::decode(&tx.hash)?)?;
+    pub fn remove(&self, batch: &mut Batch, tx: &TransactionIdentifier) -> Result<()> {
+        self.tree.remove(batch, hex::decode(&tx.hash)?);
         Ok(())
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct AccountTable {
-    tree: sled::Tree,
+    tree: Tree,
 }
 
 impl AccountTable {
-    pub fn new(tree: sled::Tree) -> Self {
+    pub fn new(tree: Tree) -> Self {
         Self { tree }
     }
 
-    pub fn get(&self, account: &AccountIdentifier) -> impl Iterator<Item = Result<TransactionRef>> {
-        let address_len = account.address.as_bytes().len();
+    /// Returns the transactions of the account newest first, starting below `before`.
+    pub fn get_rev(
+        &self,
+        account: &AccountIdentifier,
+        before: Option<&TransactionRef>,
+    ) -> impl Iterator<Item = Result<TransactionRef>> + '_ {
+        let address_len = account.address.len();
+        let start = account.address.as_bytes().to_vec();
+        let end = match before {
+            Some(tx) => account_table_key(account, tx),
+            None => {
+                let mut end = start.clone();
+                end.extend([0xff; 13]);
+                end
+            }
+        };
         self.tree
-            .scan_prefix(account.address.as_bytes())
-            .keys()
-            .map(move |key| Ok(TransactionRef::from_bytes(&key?[address_len..])))
+            .range_rev(&start, Some(&end))
+            .filter(move |entry| is_account_key(entry, address_len))
+            .map(move |entry| Ok(TransactionRef::from_bytes(&entry?.0[address_len..])))
     }
 
-    pub fn insert(&self, account: &AccountIdentifier, tx: &TransactionRef) -> Result<()> {
-        self.tree.insert(account_table_key(account, tx), &[])?;
-        Ok(())
+    pub fn insert(&self, batch: &mut Batch, account: &AccountIdentifier, tx: &TransactionRef) {
+        self.tree.insert(batch, account_table_key(account, tx), b"");
     }
 
-    pub fn len(&self, account: &AccountIdentifier) -> usize {
-        self.tree
-            .scan_prefix(account.address.as_bytes())
-            .keys()
-            .count()
+    pub fn remove(&self, batch: &mut Batch, account: &AccountIdentifier, tx: &TransactionRef) {
+        self.tree.remove(batch, account_table_key(account, tx));
     }
+}
 
-    #[allow(unused)]
-    pub fn remove(&self, account: &AccountIdentifier, tx: &TransactionRef) -> Result<()> {
-        self.tree.remove(account_table_key(account, tx))?;
-        Ok(())
-    }
+/// Skips the keys of longer addresses sharing the prefix.
+fn is_account_key(entry: &Result<Entry>, address_len: usize) -> bool {
+    entry
+        .as_ref()
+        .map(|(key, _)| key.len() == address_len + 12)
+        .unwrap_or(true)
 }
 
 fn account_table_key(account: &AccountIdentifier, tx: &TransactionRef) -> Vec<u8> {
@@ -139,264 +154,2567 @@ fn account_table_key(account: &AccountIdentifier, tx: &TransactionRef) -> Vec<u8
     key
 }
 
-#[derive(Clone)]
-pub struct Indexer<C: BlockchainClient> {
-    transaction_table: TransactionTable,
-    account_table: AccountTable,
-    client: C,
+/// Everything the indexer wrote for a block, so that it can be undone on a reorg.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct BlockRecord {
+    pub hash: String,
+    pub transactions: Vec<TransactionRecord>,
+    /// Accounts and currencies with a balance change in the block.
+    #[serde(default)]
+    pub balances: Vec<(AccountIdentifier, Currency)>,
 }
 
-impl<C: BlockchainClient> Deref for Indexer<C> {
-    type Target = C;
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TransactionRecord {
+    pub transaction_identifier: TransactionIdentifier,
+    pub accounts: Vec<AccountIdentifier>,
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.client
+/// A search condition that is indexed for every transaction it matches.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Filter {
+    Type(String),
+    Status(String),
+    Currency(Currency),
+    Coin(CoinIdentifier),
+    Success(bool),
+}
+
+impl Filter {
+    /// Returns the filters a transaction matches, without duplicates. `statuses` are the
+    /// operation statuses published by the chain.
+    pub fn from_transaction(tx: &Transaction, statuses: &[OperationStatus]) -> Vec<Self> {
+        let mut filters = vec![Self::Success(is_success(tx, statuses))];
+        for op in &tx.operations {
+            filters.push(Self::Type(op.r#type.clone()));
+            if let Some(status) = op.status.as_ref() {
+                filters.push(Self::Status(status.clone()));
+            }
+            if let Some(amount) = op.amount.as_ref() {
+                filters.push(Self::Currency(amount.currency.clone()));
+            }
+            if let Some(coin_change) = op.coin_change.as_ref() {
+                filters.push(Self::Coin(coin_change.coin_identifier.clone()));
+            }
+        }
+        let mut unique = Vec::with_capacity(filters.len());
+        for filter in filters {
+            if !unique.contains(&filter) {
+                unique.push(filter);
+            }
+        }
+        unique
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let (kind, value) = match self {
+            Self::Type(ty) => (0, ty.as_bytes().to_vec()),
+            Self::Status(status) => (1, status.as_bytes().to_vec()),
+            Self::Currency(currency) => {
+                let mut value = currency.symbol.as_bytes().to_vec();
+                value.extend(currency.decimals.to_be_bytes());
+                (2, value)
+            }
+            Self::Coin(coin) => (3, coin.identifier.as_bytes().to_vec()),
+            Self::Success(success) => (4, vec![*success as u8]),
+        };
+        let mut bytes = Vec::with_capacity(value.len() + 5);
+        bytes.push(kind);
+        bytes.extend((value.len() as u32).to_be_bytes());
+        bytes.extend(value);
+        bytes
     }
 }
 
-impl<C: BlockchainClient> Indexer<C> {
-    pub fn new(db: &Path, client: C) -> Result<Self> {
-        let db = sled::open(db)?;
-        let transaction_table = TransactionTable::new(db.open_tree("transaction_table")?);
-        let account_table = AccountTable::new(db.open_tree("account_table")?);
-        Ok(Self {
-            transaction_table,
-            account_table,
-            client,
-        })
+/// Secondary index from search filters to the transactions matching them.
+#[derive(Clone, Debug)]
+pub struct FilterTable {
+    tree: Tree,
+}
+
+impl FilterTable {
+    pub fn new(tree: Tree) -> Self {
+        Self { tree }
     }
 
-    async fn block_by_index(&self, index: u64) -> Result<Block> {
-        self.client
-            .block(&PartialBlockIdentifier {
-                index: Some(index),
-                hash: None,
-            })
-            .await
+    /// Returns the transactions matching the filter newest first, starting below `before`.
+    pub fn get_rev(
+        &self,
+        filter: &Filter,
+        before: &TransactionRef,
+    ) -> impl Iterator<Item = Result<TransactionRef>> + '_ {
+        let prefix = filter.to_bytes();
+        let end = filter_table_key(filter, before);
+        let prefix_len = prefix.len();
+        self.tree
+            .range_rev(&prefix, Some(&end))
+            .map(move |entry| Ok(TransactionRef::from_bytes(&entry?.0[prefix_len..])))
     }
 
-    async fn get(&self, tx: &TransactionRef) -> Result<Option<BlockTransaction>> {
-        let block = self.block_by_index(tx.block_index).await?;
+    pub fn insert(&self, batch: &mut Batch, filter: &Filter, tx: &TransactionRef) {
+        self.tree.insert(batch, filter_table_key(filter, tx), b"");
+    }
+
+    pub fn remove(&self, batch: &mut Batch, filter: &Filter, tx: &TransactionRef) {
+        self.tree.remove(batch, filter_table_key(filter, tx));
+    }
+}
+
+fn filter_table_key(filter: &Filter, tx: &TransactionRef) -> Vec<u8> {
+    let mut key = filter.to_bytes();
+    key.extend(tx.to_bytes());
+    key
+}
+
+/// Net change of an account's balance in a block and the running balance after it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BalanceRecord {
+    pub block_identifier: BlockIdentifier,
+    pub currency: Currency,
+    pub delta: i128,
+    pub balance: i128,
+}
+
+impl BalanceRecord {
+    pub fn to_balance_change(&self) -> BalanceChange {
+        BalanceChange {
+            block_identifier: self.block_identifier.clone(),
+            delta: Amount::new(self.delta.to_string(), self.currency.clone()),
+            balance: Amount::new(self.balance.to_string(), self.currency.clone()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BalanceTable {
+    tree: Tree,
+}
+
+impl BalanceTable {
+    pub fn new(tree: Tree) -> Self {
+        Self { tree }
+    }
+
+    /// Returns the latest balance change at or below `block_index`.
+    pub fn get(
+        &self,
+        account: &AccountIdentifier,
+        currency: &Currency,
+        block_index: u64,
+    ) -> Result<Option<BalanceRecord>> {
+        let prefix = balance_table_prefix(account, Some(currency));
+        let mut end = balance_table_key(account, currency, block_index);
+        end.push(0);
         Ok(
-            if let Some(transaction) = block
-                .transactions
-                .get(tx.transaction_index as usize)
-                .cloned()
+            if let Some((_, value)) = self
+                .tree
+                .range_rev(&prefix, Some(&end))
+                .next()
+                .transpose()?
             {
-                Some(BlockTransaction {
-                    block_identifier: block.block_identifier.clone(),
-                    transaction,
-                })
+                Some(serde_json::from_slice(&value)?)
             } else {
                 None
             },
         )
     }
 
-    async fn transaction(&self, tx: &TransactionIdentifier) -> Result<Option<BlockTransaction>> {
-        if let Some(tx) = self.transaction_table.get(tx)? {
-            self.get(&tx).await
-        } else {
-            Ok(None)
-        }
+    /// Returns the balance changes of an account, ordered by currency and block.
+    pub fn history(
+        &self,
+        account: &AccountIdentifier,
+        currency: Option<&Currency>,
+    ) -> impl Iterator<Item = Result<BalanceRecord>> + '_ {
+        self.tree
+            .scan_prefix(&balance_table_prefix(account, currency))
+            .map(|entry| Ok(serde_json::from_slice(&entry?.1)?))
     }
 
-    pub async fn sync(&self) -> Result<()> {
-        let synced_height = self.transaction_table.height()?;
-        let current_height = self.client.current_block().await?.index;
-        for block_index in (synced_height + 1)..current_height + 1 {
-            let block = self.block_by_index(block_index).await?;
-            for (transaction_index, transaction) in block.transactions.iter().enumerate() {
-                let tx = TransactionRef::new(block_index, transaction_index as _);
-                self.transaction_table
-                    .insert(&transaction.transaction_identifier, &tx)?;
-                for op in &transaction.operations {
-                    if let Some(account) = op.account.as_ref() {
-                        self.account_table.insert(account, &tx)?;
-                    }
-                }
-            }
-            log::info!("indexed blocks to {}", block_index);
-            self.transaction_table.set_height(block_index)?;
+    pub fn insert(
+        &self,
+        batch: &mut Batch,
+        account: &AccountIdentifier,
+        record: &BalanceRecord,
+    ) -> Result<()> {
+        self.tree.insert(
+            batch,
+            balance_table_key(account, &record.currency, record.block_identifier.index),
+            serde_json::to_vec(record)?,
+        );
+        Ok(())
+    }
+
+    pub fn remove(
+        &self,
+        batch: &mut Batch,
+        account: &AccountIdentifier,
+        currency: &Currency,
+        block_index: u64,
+    ) {
+        self.tree
+            .remove(batch, balance_table_key(account, currency, block_index));
+    }
+
+    /// Removes the balance changes of an account below `block_index`.
+    pub fn prune(
+        &self,
+        batch: &mut Batch,
+        account: &AccountIdentifier,
+        currency: &Currency,
+        block_index: u64,
+    ) -> Result<()> {
+        let prefix = balance_table_prefix(account, Some(currency));
+        let end = balance_table_key(account, currency, block_index);
+        for entry in self.tree.range(&prefix, Some(&end)) {
+            self.tree.remove(batch, entry?.0);
         }
         Ok(())
     }
+}
 
-    pub async fn search(
+/// Sub-accounts are keyed by the address followed by `1` and the sub-account address, so
+/// that they don't match the prefix of the account itself.
+fn balance_table_prefix(account: &AccountIdentifier, currency: Option<&Currency>) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(account.address.len() + 18);
+    prefix.extend(account.address.as_bytes());
+    if let Some(sub_account) = account.sub_account.as_ref() {
+        prefix.push(1);
+        prefix.extend(sub_account.address.as_bytes());
+    }
+    prefix.push(0);
+    if let Some(currency) = currency {
+        prefix.extend(currency.symbol.as_bytes());
+        prefix.push(0);
+        prefix.extend(currency.decimals.to_be_bytes());
+    }
+    prefix
+}
+
+fn balance_table_key(
+    account: &AccountIdentifier,
+    currency: &Currency,
+    block_index: u64,
+) -> Vec<u8> {
+    let mut key = balance_table_prefix(account, Some(currency));
+    key.extend(block_index.to_be_bytes());
+    key
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockTable {
+    tree: Tree,
+}
+
+impl BlockTable {
+    pub fn new(tree: Tree) -> Self {
+        Self { tree }
+    }
+
+    pub fn get(&self, block_index: u64) -> Result<Option<BlockRecord>> {
+        Ok(
+            if let Some(value) = self.tree.get(block_index.to_be_bytes())? {
+                Some(serde_json::from_slice(&value)?)
+            } else {
+                None
+            },
+        )
+    }
+
+    pub fn insert(&self, batch: &mut Batch, block_index: u64, record: &BlockRecord) -> Result<()> {
+        self.tree.insert(
+            batch,
+            block_index.to_be_bytes(),
+            serde_json::to_vec(record)?,
+        );
+        Ok(())
+    }
+
+    pub fn remove(&self, batch: &mut Batch, block_index: u64) {
+        self.tree.remove(batch, block_index.to_be_bytes());
+    }
+
+    /// Returns the records of the indexed blocks up to `max_block` newest first, skipping
+    /// the blocks below the start height or pruned.
+    pub fn range_rev(
         &self,
-        req: &SearchTransactionsRequest,
-    ) -> Result<SearchTransactionsResponse> {
-        let height = self.transaction_table.height()?;
-        let max_block = req.max_block.unwrap_or(height as _) as u64;
-        let mut offset = req.offset.unwrap_or(0);
-        let limit = std::cmp::min(req.limit.unwrap_or(100), 1000) as usize;
-        let account = if let Some(account) = &req.account_identifier {
-            Some(account.clone())
-        } else {
-            req.address.as_ref().map(|address| AccountIdentifier {
-                address: address.clone(),
-                sub_account: None,
-                metadata: None,
-            })
-        };
-        let matcher = Matcher {
-            op: req.operator.unwrap_or(Operator::And),
-            status: req.status.as_deref(),
-            r#type: req.r#type.as_deref(),
-            success: req.success,
-            currency: req.currency.as_ref(),
-            coin: req.coin_identifier.as_ref(),
+        max_block: u64,
+    ) -> impl Iterator<Item = Result<(u64, BlockRecord)>> + '_ {
+        let end = max_block.saturating_add(1).to_be_bytes();
+        self.tree.range_rev(&[], Some(&end)).map(|entry| {
+            let (key, value) = entry?;
+            Ok((
+                u64::from_be_bytes(key[..].try_into()?),
+                serde_json::from_slice(&value)?,
+            ))
+        })
+    }
+}
+
+/// Converted blocks, compressed so that serving them doesn't require the node.
+#[derive(Clone, Debug)]
+pub struct BlockStore {
+    blocks: Tree,
+    hashes: Tree,
+}
+
+impl BlockStore {
+    pub fn new(blocks: Tree, hashes: Tree) -> Self {
+        Self { blocks, hashes }
+    }
+
+    pub fn get(&self, block_index: u64) -> Result<Option<Block>> {
+        let Some(value) = self.blocks.get(block_index.to_be_bytes())? else {
+            return Ok(None);
         };
+        let decoder = DeflateDecoder::new(&value[..]);
+        Ok(Some(serde_json::from_reader(decoder)?))
+    }
 
-        let mut transactions = Vec::with_capacity(limit as _);
-        let (next_offset, total_count) = if let Some(tx) = req.transaction_identifier.as_ref() {
-            let total_count = if let Some(tx) = self.transaction(tx).await? {
-                if matcher.matches(&tx.transaction) {
-                    transactions.push(tx);
-                }
-                1
-            } else {
-                0
-            };
-            (None, total_count)
-        } else if let Some(account) = account.as_ref() {
-            let mut block: Option<Block> = None;
-            for tx in self.account_table.get(account).skip(offset as usize) {
-                let tx = tx?;
-                let cached = block
-                    .as_ref()
-                    .map(|block| block.block_identifier.index == tx.block_index)
-                    .unwrap_or_default();
-                if !cached {
-                    if tx.block_index > max_block {
-                        break;
-                    }
-                    block = Some(self.block_by_index(tx.block_index).await?);
-                }
-                let block = block.as_ref().unwrap();
-                offset += 1;
-                if let Some(tx) = block.transactions.get(tx.transaction_index as usize) {
-                    if matcher.matches(tx) {
-                        transactions.push(BlockTransaction {
-                            block_identifier: block.block_identifier.clone(),
-                            transaction: tx.clone(),
-                        });
-                        if transactions.len() >= limit {
-                            break;
-                        }
-                    }
-                }
-            }
-            (Some(offset), self.account_table.len(account))
+    pub fn index(&self, hash: &str) -> Result<Option<u64>> {
+        Ok(if let Some(index) = self.hashes.get(hash.as_bytes())? {
+            Some(u64::from_be_bytes(index[..].try_into()?))
         } else {
-            let mut block: Option<Block> = None;
-            for tx in self.transaction_table.iter().skip(offset as usize) {
-                let tx = tx?;
-                let cached = block
-                    .as_ref()
-                    .map(|block| block.block_identifier.index == tx.block_index)
-                    .unwrap_or_default();
-                if !cached {
-                    if tx.block_index > max_block {
-                        break;
-                    }
-                    block = Some(self.block_by_index(tx.block_index).await?);
-                }
-                let block = block.as_ref().unwrap();
-                offset += 1;
-                if let Some(tx) = block.transactions.get(tx.transaction_index as usize) {
-                    if matcher.matches(tx) {
-                        transactions.push(BlockTransaction {
-                            block_identifier: block.block_identifier.clone(),
-                            transaction: tx.clone(),
-                        });
-                        if transactions.len() >= limit {
-                            break;
-                        }
-                    }
-                }
-            }
-            (Some(offset), self.transaction_table.len())
-        };
-        Ok(SearchTransactionsResponse {
-            transactions,
-            total_count: total_count as _,
-            next_offset,
+            None
         })
     }
+
+    pub fn insert(&self, batch: &mut Batch, block: &Block) -> Result<()> {
+        let index = block.block_identifier.index.to_be_bytes();
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        serde_json::to_writer(&mut encoder, block)?;
+        self.blocks.insert(batch, index, encoder.finish()?);
+        self.hashes
+            .insert(batch, block.block_identifier.hash.as_bytes(), index);
+        Ok(())
+    }
+
+    pub fn remove(&self, batch: &mut Batch, block_identifier: &BlockIdentifier) {
+        self.blocks
+            .remove(batch, block_identifier.index.to_be_bytes());
+        self.hashes.remove(batch, block_identifier.hash.as_bytes());
+    }
 }
 
-struct Matcher<'a> {
-    op: Operator,
-    r#type: Option<&'a str>,
-    status: Option<&'a str>,
-    currency: Option<&'a Currency>,
-    coin: Option<&'a CoinIdentifier>,
-    success: Option<bool>,
+/// An index that searches are answered from.
+#[derive(Clone, Copy, Debug)]
+enum Index<'a> {
+    All,
+    Account(&'a AccountIdentifier),
+    Filter(&'a Filter),
 }
 
-impl<'a> Matcher<'a> {
-    fn matches(&self, tx: &Transaction) -> bool {
-        let mut matches_success = false;
-        if let Some(success) = self.success {
-            matches_success = success == is_success(tx);
+impl Index<'_> {
+    const ALL: u8 = 0;
+    const ACCOUNT: u8 = 1;
+    const FILTER: u8 = 2;
+
+    fn count_key(&self) -> Vec<u8> {
+        match self {
+            Self::All => count_key(Self::ALL, &[]),
+            Self::Account(account) => count_key(Self::ACCOUNT, account.address.as_bytes()),
+            Self::Filter(filter) => count_key(Self::FILTER, &filter.to_bytes()),
         }
-        let mut matches_type = false;
-        let mut matches_status = false;
-        let mut matches_currency = false;
-        let mut matches_coin = false;
-        for op in &tx.operations {
-            if let Some(ty) = self.r#type {
-                if ty == op.r#type {
-                    matches_type = true;
-                }
-            }
-            if let Some(status) = self.status {
-                if let Some(op_status) = op.status.as_deref() {
-                    if status == op_status {
-                        matches_status = true;
-                    }
-                }
+    }
+}
+
+/// Adds the change of the counts when a transaction is added (`1`) or removed (`-1`).
+fn count_transaction(counts: &mut BTreeMap<Vec<u8>, i64>, record: &TransactionRecord, change: i64) {
+    let indexes = std::iter::once(Index::All)
+        .chain(record.accounts.iter().map(Index::Account))
+        .chain(record.filters.iter().map(Index::Filter));
+    for index in indexes {
+        *counts.entry(index.count_key()).or_default() += change;
+    }
+}
+
+fn count_key(kind: u8, value: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(value.len() + 1);
+    key.push(kind);
+    key.extend(value);
+    key
+}
+
+/// Number of transactions in each index, so that searches don't count their results.
+#[derive(Clone, Debug)]
+pub struct CountTable {
+    tree: Tree,
+}
+
+impl CountTable {
+    pub fn new(tree: Tree) -> Self {
+        Self { tree }
+    }
+
+    fn get(&self, index: &Index) -> Result<u64> {
+        Ok(if let Some(count) = self.tree.get(index.count_key())? {
+            u64::from_be_bytes(count[..].try_into()?)
+        } else {
+            0
+        })
+    }
+
+    /// Adds the changes to the counts, the changes of a batch have to be added at once.
+    fn add(&self, batch: &mut Batch, changes: &BTreeMap<Vec<u8>, i64>) -> Result<()> {
+        for (key, change) in changes {
+            let count = match self.tree.get(key)? {
+                Some(count) => u64::from_be_bytes(count[..].try_into()?),
+                None => 0,
+            };
+            match (count as i64 + change) as u64 {
+                0 => self.tree.remove(batch, key),
+                count => self.tree.insert(batch, key, count.to_be_bytes()),
             }
-            if let Some(currency) = self.currency {
-                if let Some(amount) = op.amount.as_ref() {
-                    if currency == &amount.currency {
-                        matches_currency = true;
-                    }
+        }
+        Ok(())
+    }
+}
+
+/// Transactions ordered newest first.
+type Refs<'a> = Box<dyn Iterator<Item = Result<TransactionRef>> + Send + 'a>;
+
+/// Merges streams of transactions ordered newest first into the transactions of all
+/// streams with `Operator::And`, or of any stream with `Operator::Or`.
+struct Merge<'a> {
+    streams: Vec<Peekable<Refs<'a>>>,
+    operator: Operator,
+}
+
+impl<'a> Merge<'a> {
+    fn refs(streams: Vec<Refs<'a>>, operator: Operator) -> Refs<'a> {
+        if streams.len() == 1 {
+            return streams.into_iter().next().unwrap();
+        }
+        Box::new(Self {
+            streams: streams.into_iter().map(Iterator::peekable).collect(),
+            operator,
+        })
+    }
+}
+
+impl Iterator for Merge<'_> {
+    type Item = Result<TransactionRef>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut heads = Vec::with_capacity(self.streams.len());
+            for stream in &mut self.streams {
+                match stream.peek() {
+                    Some(Ok(tx)) => heads.push(Some(*tx)),
+                    Some(Err(_)) => return stream.next(),
+                    None => heads.push(None),
                 }
             }
-            if let Some(coin) = self.coin {
-                if let Some(coin_change) = op.coin_change.as_ref() {
-                    if coin == &coin_change.coin_identifier {
-                        matches_coin = true;
-                    }
+            let target = match self.operator {
+                // a transaction is in all streams once they are advanced to the oldest head
+                Operator::And => heads
+                    .iter()
+                    .copied()
+                    .collect::<Option<Vec<_>>>()?
+                    .into_iter()
+                    .min()?,
+                Operator::Or => heads.iter().flatten().max().copied()?,
+            };
+            let matched = match self.operator {
+                Operator::And => heads.iter().all(|head| *head == Some(target)),
+                Operator::Or => true,
+            };
+            for (stream, head) in self.streams.iter_mut().zip(&heads) {
+                let advance = match self.operator {
+                    Operator::And => matched || *head != Some(target),
+                    Operator::Or => *head == Some(target),
+                };
+                if advance {
+                    stream.next();
                 }
             }
-        }
-        match self.op {
-            Operator::And => {
-                (matches_success || self.success.is_none())
-                    && (matches_type || self.r#type.is_none())
-                    && (matches_status || self.status.is_none())
-                    && (matches_currency || self.currency.is_none())
-                    && (matches_coin || self.coin.is_none())
-            }
-            Operator::Or => {
-                matches_success
-                    || matches_type
-                    || matches_status
-                    || matches_currency
-                    || matches_coin
+            if matched {
+                return Some(Ok(target));
             }
         }
     }
 }
 
-// TODO: is this really correct?
-fn is_success(tx: &Transaction) -> bool {
-    if let Some(op) = tx.operations.last() {
-        !op.r#type.to_lowercase().contains("fail")
-    } else {
-        false
+/// Balance change of an account in a block that doesn't add up to its operations.
+#[derive(Clone, Debug, Serialize)]
+pub struct Mismatch {
+    pub block_identifier: BlockIdentifier,
+    pub account_identifier: AccountIdentifier,
+    /// Sum of the operation amounts of the account in the block.
+    pub computed: i128,
+    /// Balance change reported by the node.
+    pub actual: i128,
+    pub operations: Vec<Operation>,
+}
+
+/// Indexer state that isn't tied to a block.
+#[derive(Clone, Debug)]
+pub struct MetaTable {
+    tree: Tree,
+}
+
+impl MetaTable {
+    const FINALIZED_HEIGHT: &'static [u8] = b"finalized_height";
+    const SCHEMA_VERSION: &'static [u8] = b"schema_version";
+    const START_HEIGHT: &'static [u8] = b"start_height";
+    const OLDEST_HEIGHT: &'static [u8] = b"oldest_height";
+    const REINDEXED_FROM: &'static [u8] = b"reindexed_from";
+
+    pub fn new(tree: Tree) -> Self {
+        Self { tree }
+    }
+
+    pub fn finalized_height(&self) -> Result<u64> {
+        Ok(
+            if let Some(height) = self.tree.get(Self::FINALIZED_HEIGHT)? {
+                u64::from_be_bytes(height[..].try_into()?)
+            } else {
+                0
+            },
+        )
+    }
+
+    pub fn set_finalized_height(&self, batch: &mut Batch, height: u64) {
+        self.tree
+            .insert(batch, Self::FINALIZED_HEIGHT, height.to_be_bytes());
+    }
+
+    /// Layout version of the tables, `None` for indexes created before it was recorded.
+    pub fn schema_version(&self) -> Result<Option<u32>> {
+        Ok(
+            if let Some(version) = self.tree.get(Self::SCHEMA_VERSION)? {
+                Some(u32::from_be_bytes(version[..].try_into()?))
+            } else {
+                None
+            },
+        )
+    }
+
+    pub fn set_schema_version(&self, batch: &mut Batch, version: u32) {
+        self.tree
+            .insert(batch, Self::SCHEMA_VERSION, version.to_be_bytes());
+    }
+
+    /// First block of an index that didn't start at genesis.
+    pub fn start_height(&self) -> Result<Option<u64>> {
+        self.height(Self::START_HEIGHT)
+    }
+
+    pub fn set_start_height(&self, batch: &mut Batch, height: u64) {
+        self.tree
+            .insert(batch, Self::START_HEIGHT, height.to_be_bytes());
+    }
+
+    /// Oldest block that wasn't pruned, `None` when the index holds all blocks since
+    /// genesis.
+    pub fn oldest_height(&self) -> Result<Option<u64>> {
+        self.height(Self::OLDEST_HEIGHT)
+    }
+
+    pub fn set_oldest_height(&self, batch: &mut Batch, height: u64) {
+        self.tree
+            .insert(batch, Self::OLDEST_HEIGHT, height.to_be_bytes());
+    }
+
+    /// Height of the last completed reindex.
+    pub fn reindexed_from(&self) -> Result<Option<u64>> {
+        self.height(Self::REINDEXED_FROM)
+    }
+
+    pub fn set_reindexed_from(&self, batch: &mut Batch, height: u64) {
+        self.tree
+            .insert(batch, Self::REINDEXED_FROM, height.to_be_bytes());
+    }
+
+    fn height(&self, key: &[u8]) -> Result<Option<u64>> {
+        Ok(if let Some(height) = self.tree.get(key)? {
+            Some(u64::from_be_bytes(height[..].try_into()?))
+        } else {
+            None
+        })
+    }
+}
+
+/// Version of the table layout, bumped with a new migration whenever the layout changes.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// `MIGRATIONS[version]` upgrades an index from `version` to `version + 1`.
+const MIGRATIONS: [fn(&dyn Storage) -> Result<()>; SCHEMA_VERSION as usize] =
+    [drop_index, count_transactions];
+
+/// Indexes created before the schema was versioned lack the balance and filter tables,
+/// they are dropped and synced again from genesis.
+fn drop_index(storage: &dyn Storage) -> Result<()> {
+    for table in Table::ALL {
+        if table != Table::Meta {
+            storage.clear(table)?;
+        }
+    }
+    Ok(())
+}
+
+/// Counts the transactions of every index, which searches report as their total count.
+fn count_transactions(storage: &dyn Storage) -> Result<()> {
+    storage.clear(Table::Counts)?;
+    let mut counts = BTreeMap::<Vec<u8>, u64>::new();
+    for (table, kind) in [
+        (Table::Accounts, Index::ACCOUNT),
+        (Table::Filters, Index::FILTER),
+    ] {
+        for entry in storage.range(table, &[], None, false) {
+            let (key, _) = entry?;
+            *counts
+                .entry(count_key(kind, &key[..key.len() - 12]))
+                .or_default() += 1;
+        }
+    }
+    for entry in storage.range(Table::Blocks, &[], None, false) {
+        let record: BlockRecord = serde_json::from_slice(&entry?.1)?;
+        *counts.entry(Index::All.count_key()).or_default() += record.transactions.len() as u64;
+    }
+    let mut batch = Batch::default();
+    for (key, count) in counts {
+        batch.insert(Table::Counts, &key, &count.to_be_bytes());
+    }
+    storage.commit(batch)
+}
+
+#[derive(Clone, Debug)]
+pub struct EventTable {
+    tree: Tree,
+    /// Sequence of the next event, read from the table on the first push.
+    next_sequence: Arc<Mutex<Option<u64>>>,
+}
+
+impl EventTable {
+    pub fn new(tree: Tree) -> Self {
+        Self {
+            tree,
+            next_sequence: Default::default(),
+        }
+    }
+
+    pub fn max_sequence(&self) -> Result<Option<u64>> {
+        Ok(if let Some((key, _)) = self.tree.last()? {
+            Some(u64::from_be_bytes(key[..].try_into()?))
+        } else {
+            None
+        })
+    }
+
+    /// Appends an event after the last pushed one. If the batch isn't committed the
+    /// sequence has to be [`reset`](Self::reset).
+    pub fn push(
+        &self,
+        batch: &mut Batch,
+        r#type: BlockEventType,
+        block_identifier: BlockIdentifier,
+    ) -> Result<BlockEvent> {
+        let mut next_sequence = self.next_sequence.lock().unwrap();
+        let sequence = match *next_sequence {
+            Some(sequence) => sequence,
+            None => self.max_sequence()?.map(|seq| seq + 1).unwrap_or_default(),
+        };
+        *next_sequence = Some(sequence + 1);
+        let event = BlockEvent {
+            sequence: sequence as _,
+            block_identifier,
+            r#type,
+        };
+        self.tree
+            .insert(batch, sequence.to_be_bytes(), serde_json::to_vec(&event)?);
+        Ok(event)
+    }
+
+    /// Rereads the sequence of the next event from the table.
+    pub fn reset(&self) {
+        *self.next_sequence.lock().unwrap() = None;
+    }
+
+    pub fn range(
+        &self,
+        offset: u64,
+        limit: usize,
+    ) -> impl Iterator<Item = Result<BlockEvent>> + '_ {
+        self.tree
+            .range(&offset.to_be_bytes(), None)
+            .take(limit)
+            .map(|entry| Ok(serde_json::from_slice(&entry?.1)?))
+    }
+}
+
+/// First block indexed by an empty index.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum StartHeight {
+    #[default]
+    Genesis,
+    /// A fixed block index.
+    Height(u64),
+    /// A number of blocks below the sync target.
+    Depth(u64),
+}
+
+#[derive(Clone)]
+pub struct Indexer<C: BlockchainClient> {
+    transaction_table: TransactionTable,
+    account_table: AccountTable,
+    count_table: CountTable,
+    block_table: BlockTable,
+    event_table: EventTable,
+    block_store: BlockStore,
+    balance_table: BalanceTable,
+    filter_table: FilterTable,
+    meta_table: MetaTable,
+    storage: Arc<dyn Storage>,
+    client: C,
+    sync_concurrency: usize,
+    finalized_only: bool,
+    start_height: StartHeight,
+    retention: Option<u64>,
+}
+
+impl<C: BlockchainClient> Deref for Indexer<C> {
+    type Target = C;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl<C: BlockchainClient> Indexer<C> {
+    pub fn new(storage: Arc<dyn Storage>, client: C) -> Self {
+        let tree = |table| Tree::new(storage.clone(), table);
+        Self {
+            transaction_table: TransactionTable::new(tree(Table::Transactions)),
+            account_table: AccountTable::new(tree(Table::Accounts)),
+            count_table: CountTable::new(tree(Table::Counts)),
+            block_table: BlockTable::new(tree(Table::Blocks)),
+            event_table: EventTable::new(tree(Table::Events)),
+            block_store: BlockStore::new(tree(Table::BlockStore), tree(Table::BlockHashes)),
+            balance_table: BalanceTable::new(tree(Table::Balances)),
+            filter_table: FilterTable::new(tree(Table::Filters)),
+            meta_table: MetaTable::new(tree(Table::Meta)),
+            storage,
+            client,
+            sync_concurrency: 1,
+            finalized_only: false,
+            start_height: StartHeight::Genesis,
+            retention: None,
+        }
+    }
+
+    /// Sets the number of blocks fetched in parallel while catching up with the node.
+    pub fn with_sync_concurrency(mut self, sync_concurrency: usize) -> Self {
+        self.sync_concurrency = sync_concurrency.max(1);
+        self
+    }
+
+    /// Only indexes blocks that can no longer be reverted by a reorg.
+    pub fn with_finalized_only(mut self, finalized_only: bool) -> Self {
+        self.finalized_only = finalized_only;
+        self
+    }
+
+    pub fn finalized_only(&self) -> bool {
+        self.finalized_only
+    }
+
+    /// Sets the first block indexed when the index is empty. Balances are accumulated
+    /// from that block, so they are no longer absolute.
+    pub fn with_start_height(mut self, start_height: StartHeight) -> Self {
+        self.start_height = start_height;
+        self
+    }
+
+    /// Prunes the blocks that are more than `retention` blocks below the indexed height.
+    pub fn with_retention(mut self, retention: Option<u64>) -> Self {
+        self.retention = retention.map(|retention| retention.max(1));
+        self
+    }
+
+    /// Upgrades the index to the current schema version, must be called before syncing.
+    pub fn migrate(&self) -> Result<()> {
+        let version = match self.meta_table.schema_version()? {
+            Some(version) => version,
+            None if self.transaction_table.height()? == 0 => SCHEMA_VERSION,
+            None => 0,
+        };
+        anyhow::ensure!(
+            version <= SCHEMA_VERSION,
+            "index schema version {} is newer than the supported version {}",
+            version,
+            SCHEMA_VERSION
+        );
+        for version in version..SCHEMA_VERSION {
+            log::info!(
+                "migrating index from schema version {} to {}",
+                version,
+                version + 1
+            );
+            MIGRATIONS[version as usize](&*self.storage)?;
+            let mut batch = Batch::default();
+            self.meta_table.set_schema_version(&mut batch, version + 1);
+            self.storage.commit(batch)?;
+        }
+        if self.meta_table.schema_version()?.is_none() {
+            let mut batch = Batch::default();
+            self.meta_table.set_schema_version(&mut batch, version);
+            self.storage.commit(batch)?;
+        }
+        self.event_table.reset();
+        Ok(())
+    }
+
+    /// Removes the indexed blocks from `height` onwards, the next sync indexes them again.
+    ///
+    /// The completed reindex is recorded, so that reindexing again from the same height is
+    /// skipped. Pruned indexes can't be reindexed below their oldest block, the balances
+    /// carried forward by pruning aren't part of any indexed block.
+    pub fn reindex_from(&self, height: u64) -> Result<Vec<BlockEvent>> {
+        anyhow::ensure!(height > 0, "the genesis block isn't indexed");
+        if self.meta_table.reindexed_from()? == Some(height) {
+            log::info!("already reindexed from {}", height);
+            return Ok(vec![]);
+        }
+        let oldest = self.meta_table.oldest_height()?;
+        if let Some(oldest) = oldest {
+            let pruned = oldest > self.meta_table.start_height()?.unwrap_or(1);
+            anyhow::ensure!(
+                !pruned || height >= oldest,
+                "the index was pruned to block {}, it can't be reindexed from {}",
+                oldest,
+                height
+            );
+        }
+        let events = self.rollback(height - 1)?;
+        let mut batch = Batch::default();
+        if oldest.map(|oldest| oldest > height).unwrap_or_default() {
+            self.meta_table.set_oldest_height(&mut batch, height);
+        }
+        self.meta_table.set_reindexed_from(&mut batch, height);
+        self.storage.commit(batch)?;
+        Ok(events)
+    }
+
+    async fn fetch_block(&self, index: u64) -> Result<Block> {
+        self.client
+            .block(&PartialBlockIdentifier {
+                index: Some(index),
+                hash: None,
+            })
+            .await
+    }
+
+    async fn block_by_index(&self, index: u64) -> Result<Block> {
+        match self.block_store.get(index)? {
+            Some(block) => Ok(block),
+            None => self.fetch_block(index).await,
+        }
+    }
+
+    /// Returns the block from the block store, only blocks that are not indexed yet are
+    /// fetched from the node.
+    pub async fn block(&self, block_identifier: &PartialBlockIdentifier) -> Result<Block> {
+        let index = match (block_identifier.index, block_identifier.hash.as_deref()) {
+            (_, Some(hash)) => self.block_store.index(hash)?,
+            (Some(index), None) => Some(index),
+            (None, None) => None,
+        };
+        if let Some(index) = index {
+            if let Some(block) = self.block_store.get(index)? {
+                return Ok(block);
+            }
+        }
+        self.client.block(block_identifier).await
+    }
+
+    /// Returns the transaction from the block store, falling back to the node for blocks
+    /// that are not indexed yet.
+    pub async fn block_transaction(
+        &self,
+        block_identifier: &BlockIdentifier,
+        tx: &TransactionIdentifier,
+    ) -> Result<Transaction> {
+        if let Some(block) = self.block_store.get(block_identifier.index)? {
+            if block.block_identifier.hash == block_identifier.hash {
+                return block
+                    .transactions
+                    .into_iter()
+                    .find(|transaction| transaction.transaction_identifier == *tx)
+                    .ok_or_else(|| anyhow::anyhow!("transaction not found"));
+            }
+        }
+        self.client.block_transaction(block_identifier, tx).await
+    }
+
+    async fn get(&self, tx: &TransactionRef) -> Result<Option<BlockTransaction>> {
+        let block = self.block_by_index(tx.block_index).await?;
+        Ok(
+            if let Some(transaction) = block
+                .transactions
+                .get(tx.transaction_index as usize)
+                .cloned()
+            {
+                Some(block_transaction(
+                    &block.block_identifier,
+                    transaction,
+                    tx.block_index <= self.finalized_height()?,
+                ))
+            } else {
+                None
+            },
+        )
+    }
+
+    async fn transaction(&self, tx: &TransactionIdentifier) -> Result<Option<BlockTransaction>> {
+        if let Some(tx) = self.transaction_table.get(tx)? {
+            self.get(&tx).await
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Indexes all blocks up to the current block of the node.
+    ///
+    /// Up to `sync_concurrency` blocks are fetched in parallel, but they are committed
+    /// strictly in order, each block together with the indexed height and its event.
+    /// When the parent of the next block doesn't match the indexed chain, the indexed
+    /// blocks are rolled back to the fork point before the canonical chain is indexed.
+    /// Every removed and added block is appended to the event log, the new events are
+    /// returned in the order they were processed.
+    pub async fn sync(&self) -> Result<Vec<BlockEvent>> {
+        let mut events = vec![];
+        let finalized = self.client.finalized_block().await?;
+        // the current block is read even when only finalized blocks are indexed, so that
+        // the metered client keeps reporting the chain tip
+        let current = self.client.current_block().await?;
+        let target_height = if self.finalized_only {
+            finalized.index
+        } else {
+            current.index
+        };
+        self.start(target_height)?;
+        'sync: loop {
+            let start = self.transaction_table.height()? + 1;
+            let mut blocks = stream::iter(start..=target_height)
+                .map(|index| self.fetch_block(index))
+                .buffered(self.sync_concurrency);
+            while let Some(block) = blocks.next().await {
+                let block = block?;
+                let block_index = block.block_identifier.index;
+                if let Some(parent) = self.block_table.get(block_index - 1)? {
+                    if parent.hash != block.parent_block_identifier.hash {
+                        let fork_index = self.find_fork_point(block_index - 1).await?;
+                        log::warn!(
+                            "reorg detected at block {}, rolling back to {}",
+                            block_index,
+                            fork_index
+                        );
+                        events.extend(self.rollback(fork_index)?);
+                        continue 'sync;
+                    }
+                }
+                events.push(self.index_block(&block)?);
+                log::info!("indexed blocks to {}", block_index);
+            }
+            if let Some(retention) = self.retention {
+                let height = self.transaction_table.height()?;
+                self.prune(height.saturating_sub(retention) + 1)?;
+            }
+            let mut batch = Batch::default();
+            self.meta_table
+                .set_finalized_height(&mut batch, finalized.index);
+            self.storage.commit(batch)?;
+            return Ok(events);
+        }
+    }
+
+    fn index_block(&self, block: &Block) -> Result<BlockEvent> {
+        let mut batch = Batch::default();
+        let block_index = block.block_identifier.index;
+        let mut record = BlockRecord {
+            hash: block.block_identifier.hash.clone(),
+            transactions: Vec::with_capacity(block.transactions.len()),
+            balances: vec![],
+        };
+        let statuses = self.client.operation_statuses();
+        let mut deltas: Vec<(AccountIdentifier, Currency, i128)> = vec![];
+        let mut counts = BTreeMap::new();
+        for (transaction_index, transaction) in block.transactions.iter().enumerate() {
+            let tx = TransactionRef::new(block_index, transaction_index as _);
+            self.transaction_table
+                .insert(&mut batch, &transaction.transaction_identifier, &tx)?;
+            let mut accounts: Vec<AccountIdentifier> = vec![];
+            for op in &transaction.operations {
+                if let Some(account) = op.account.as_ref() {
+                    self.account_table.insert(&mut batch, account, &tx);
+                    if !accounts.iter().any(|a| a.address == account.address) {
+                        accounts.push(account.clone());
+                    }
+                }
+                let failed = is_failed(op, &statuses);
+                if let (Some(account), Some(amount), false) = (&op.account, &op.amount, failed) {
+                    let value = amount.value.parse::<i128>().map_err(|err| {
+                        anyhow::anyhow!(
+                            "invalid amount {} in transaction {}: {}",
+                            amount.value,
+                            transaction.transaction_identifier.hash,
+                            err
+                        )
+                    })?;
+                    match deltas.iter_mut().find(|(a, c, _)| {
+                        a.address == account.address
+                            && a.sub_account == account.sub_account
+                            && *c == amount.currency
+                    }) {
+                        Some((_, _, delta)) => *delta += value,
+                        None => deltas.push((account.clone(), amount.currency.clone(), value)),
+                    }
+                }
+            }
+            let filters = Filter::from_transaction(transaction, &statuses);
+            for filter in &filters {
+                self.filter_table.insert(&mut batch, filter, &tx);
+            }
+            let transaction = TransactionRecord {
+                transaction_identifier: transaction.transaction_identifier.clone(),
+                accounts,
+                filters,
+            };
+            count_transaction(&mut counts, &transaction, 1);
+            record.transactions.push(transaction);
+        }
+        self.count_table.add(&mut batch, &counts)?;
+        for (account, currency, delta) in deltas {
+            let balance = self
+                .balance_table
+                .get(&account, &currency, block_index)?
+                .map(|record| record.balance)
+                .unwrap_or_default();
+            let balance_record = BalanceRecord {
+                block_identifier: block.block_identifier.clone(),
+                currency: currency.clone(),
+                delta,
+                balance: balance + delta,
+            };
+            self.balance_table
+                .insert(&mut batch, &account, &balance_record)?;
+            record.balances.push((account, currency));
+        }
+        self.block_table.insert(&mut batch, block_index, &record)?;
+        self.block_store.insert(&mut batch, block)?;
+        self.transaction_table.set_height(&mut batch, block_index);
+        let event = self.event_table.push(
+            &mut batch,
+            BlockEventType::Added,
+            block.block_identifier.clone(),
+        )?;
+        self.commit_event(batch)?;
+        Ok(event)
+    }
+
+    /// Returns the highest indexed block at or below `block_index` that is still part
+    /// of the canonical chain.
+    async fn find_fork_point(&self, mut block_index: u64) -> Result<u64> {
+        while block_index > 0 {
+            let Some(record) = self.block_table.get(block_index)? else {
+                break;
+            };
+            let block = self
+                .client
+                .block_identifier(&PartialBlockIdentifier {
+                    index: Some(block_index),
+                    hash: None,
+                })
+                .await?;
+            if block.hash == record.hash {
+                break;
+            }
+            block_index -= 1;
+        }
+        Ok(block_index)
+    }
+
+    /// Removes all indexed blocks above `fork_index`, newest first.
+    fn rollback(&self, fork_index: u64) -> Result<Vec<BlockEvent>> {
+        let mut events = vec![];
+        let mut block_index = self.transaction_table.height()?;
+        while block_index > fork_index {
+            let mut batch = Batch::default();
+            if let Some(record) = self.block_table.get(block_index)? {
+                self.remove_transactions(&mut batch, block_index, &record)?;
+                for (account, currency) in &record.balances {
+                    self.balance_table
+                        .remove(&mut batch, account, currency, block_index);
+                }
+                let block_identifier = BlockIdentifier::new(block_index, record.hash);
+                self.block_table.remove(&mut batch, block_index);
+                self.block_store.remove(&mut batch, &block_identifier);
+                events.push(self.event_table.push(
+                    &mut batch,
+                    BlockEventType::Removed,
+                    block_identifier,
+                )?);
+            }
+            block_index -= 1;
+            self.transaction_table.set_height(&mut batch, block_index);
+            self.commit_event(batch)?;
+        }
+        Ok(events)
+    }
+
+    /// Commits a batch with a pushed event, the event sequence is reset if it fails.
+    fn commit_event(&self, batch: Batch) -> Result<()> {
+        let result = self.storage.commit(batch);
+        if result.is_err() {
+            self.event_table.reset();
+        }
+        result
+    }
+
+    fn remove_transactions(
+        &self,
+        batch: &mut Batch,
+        block_index: u64,
+        record: &BlockRecord,
+    ) -> Result<()> {
+        let mut counts = BTreeMap::new();
+        for (transaction_index, transaction) in record.transactions.iter().enumerate() {
+            let tx = TransactionRef::new(block_index, transaction_index as _);
+            for account in &transaction.accounts {
+                self.account_table.remove(batch, account, &tx);
+            }
+            for filter in &transaction.filters {
+                self.filter_table.remove(batch, filter, &tx);
+            }
+            self.transaction_table
+                .remove(batch, &transaction.transaction_identifier)?;
+            count_transaction(&mut counts, transaction, -1);
+        }
+        self.count_table.add(batch, &counts)
+    }
+
+    /// Moves an empty index to the configured start height.
+    fn start(&self, target_height: u64) -> Result<()> {
+        if self.transaction_table.height()? > 0 || self.meta_table.oldest_height()?.is_some() {
+            return Ok(());
+        }
+        let start_height = match self.start_height {
+            StartHeight::Genesis => return Ok(()),
+            StartHeight::Height(height) => height,
+            StartHeight::Depth(depth) => target_height.saturating_sub(depth),
+        };
+        if start_height <= 1 {
+            return Ok(());
+        }
+        log::info!("starting the index at block {}", start_height);
+        let mut batch = Batch::default();
+        self.transaction_table
+            .set_height(&mut batch, start_height - 1);
+        self.meta_table.set_start_height(&mut batch, start_height);
+        self.meta_table.set_oldest_height(&mut batch, start_height);
+        self.storage.commit(batch)?;
+        Ok(())
+    }
+
+    /// Removes the indexed blocks below `oldest`, oldest first. The latest balance change
+    /// of every account below `oldest` is kept, so that balances stay available.
+    fn prune(&self, oldest: u64) -> Result<()> {
+        let mut block_index = self.meta_table.oldest_height()?.unwrap_or(1);
+        while block_index < oldest {
+            let mut batch = Batch::default();
+            if let Some(record) = self.block_table.get(block_index)? {
+                self.remove_transactions(&mut batch, block_index, &record)?;
+                for (account, currency) in &record.balances {
+                    if let Some(latest) = self.balance_table.get(account, currency, oldest)? {
+                        let latest = latest.block_identifier.index;
+                        self.balance_table
+                            .prune(&mut batch, account, currency, latest)?;
+                    }
+                }
+                self.block_table.remove(&mut batch, block_index);
+                self.block_store
+                    .remove(&mut batch, &BlockIdentifier::new(block_index, record.hash));
+            }
+            block_index += 1;
+            self.meta_table.set_oldest_height(&mut batch, block_index);
+            self.storage.commit(batch)?;
+        }
+        Ok(())
+    }
+
+    /// Balance of the account at an indexed block, computed from the balance history.
+    /// Returns `None` when the block isn't indexed or when the index didn't start at
+    /// genesis.
+    pub fn balance_at(
+        &self,
+        account: &AccountIdentifier,
+        currency: &Currency,
+        block_index: u64,
+    ) -> Result<Option<i128>> {
+        if block_index > self.transaction_table.height()?
+            || block_index < self.meta_table.oldest_height()?.unwrap_or_default()
+            || self.meta_table.start_height()?.is_some()
+        {
+            return Ok(None);
+        }
+        let record = self.balance_table.get(account, currency, block_index)?;
+        Ok(Some(
+            record.map(|record| record.balance).unwrap_or_default(),
+        ))
+    }
+
+    pub fn balance_history(
+        &self,
+        req: &AccountBalanceHistoryRequest,
+    ) -> Result<AccountBalanceHistoryResponse> {
+        let min_block = req.min_block.unwrap_or_default() as u64;
+        let max_block = match req.max_block {
+            Some(max_block) => max_block as u64,
+            None => self.transaction_table.height()?,
+        };
+        let offset = usize::try_from(req.offset.unwrap_or_default())?;
+        let limit = usize::try_from(req.limit.unwrap_or(100))?.min(1000);
+        let currencies = match &req.currencies {
+            Some(currencies) => currencies.iter().map(Some).collect(),
+            None => vec![None],
+        };
+        let changes = currencies.into_iter().flat_map(|currency| {
+            self.balance_table
+                .history(&req.account_identifier, currency)
+        });
+        let mut balances = Vec::with_capacity(limit);
+        let mut skipped = 0;
+        let mut next_offset = None;
+        for record in changes {
+            let record = record?;
+            let index = record.block_identifier.index;
+            if index < min_block || index > max_block {
+                continue;
+            }
+            if skipped < offset {
+                skipped += 1;
+                continue;
+            }
+            if balances.len() == limit {
+                next_offset = Some((offset + limit) as _);
+                break;
+            }
+            balances.push(record.to_balance_change());
+        }
+        Ok(AccountBalanceHistoryResponse {
+            balances,
+            next_offset,
+        })
+    }
+
+    /// Index of the highest indexed block.
+    pub fn height(&self) -> Result<u64> {
+        self.transaction_table.height()
+    }
+
+    /// Height of the latest finalized block seen by the last sync.
+    pub fn finalized_height(&self) -> Result<u64> {
+        self.meta_table.finalized_height()
+    }
+
+    /// Oldest indexed block when the index doesn't reach back to genesis, because it
+    /// started at a later height or older blocks were pruned.
+    pub fn oldest_block(&self) -> Result<Option<BlockIdentifier>> {
+        let Some(block_index) = self.meta_table.oldest_height()? else {
+            return Ok(None);
+        };
+        Ok(self
+            .block_table
+            .get(block_index)?
+            .map(|record| BlockIdentifier::new(block_index, record.hash)))
+    }
+
+    /// Accounts to reconcile at an indexed block, the tracked accounts and up to `sample`
+    /// accounts with a balance change in the block.
+    pub fn reconciliation_accounts(
+        &self,
+        block_index: u64,
+        tracked: &[AccountIdentifier],
+        sample: usize,
+    ) -> Result<Vec<AccountIdentifier>> {
+        let mut accounts = tracked.to_vec();
+        let Some(record) = self.block_table.get(block_index)? else {
+            return Ok(accounts);
+        };
+        let currency = self.client.config().currency();
+        let changed = record
+            .balances
+            .into_iter()
+            // the node reports the balance of the account, not of its sub-accounts
+            .filter(|(account, c)| {
+                *c == currency && account.sub_account.is_none() && !tracked.contains(account)
+            })
+            .map(|(account, _)| account)
+            .collect::<Vec<_>>();
+        // rotates through the changed accounts so that every account gets sampled
+        let offset = block_index as usize % changed.len().max(1);
+        accounts.extend(
+            changed
+                .iter()
+                .cycle()
+                .skip(offset)
+                .take(sample.min(changed.len()))
+                .cloned(),
+        );
+        Ok(accounts)
+    }
+
+    /// Compares the balance changes computed from the operations of an indexed block with
+    /// the balances reported by the node before and after the block.
+    pub async fn reconcile(
+        &self,
+        block_index: u64,
+        accounts: &[AccountIdentifier],
+    ) -> Result<Vec<Mismatch>> {
+        let config = self.client.config();
+        anyhow::ensure!(
+            config.historical_balance_lookup,
+            "reconciliation requires historical balance lookups"
+        );
+        let Some(record) = self.block_table.get(block_index)? else {
+            anyhow::bail!("block {} is not indexed", block_index);
+        };
+        let block_identifier = BlockIdentifier::new(block_index, record.hash);
+        let parent_identifier = match self.block_table.get(block_index - 1)? {
+            Some(parent) => BlockIdentifier::new(block_index - 1, parent.hash),
+            None if block_index > 1 => {
+                self.client
+                    .block_identifier(&PartialBlockIdentifier {
+                        index: Some(block_index - 1),
+                        hash: None,
+                    })
+                    .await?
+            }
+            None => self.client.genesis_block().clone(),
+        };
+        let currency = config.currency();
+        let mut block: Option<Block> = None;
+        let mut mismatches = vec![];
+        for account in accounts {
+            let computed = match self.balance_table.get(account, &currency, block_index)? {
+                Some(record) if record.block_identifier.index == block_index => record.delta,
+                _ => 0,
+            };
+            let address = Address::new(config.address_format, account.address.clone());
+            let before = self.client.balance(&address, &parent_identifier).await?;
+            let after = self.client.balance(&address, &block_identifier).await?;
+            let actual = after as i128 - before as i128;
+            if actual == computed {
+                continue;
+            }
+            if block.is_none() {
+                block = Some(self.block_by_index(block_index).await?);
+            }
+            let operations = block
+                .iter()
+                .flat_map(|block| &block.transactions)
+                .flat_map(|transaction| &transaction.operations)
+                .filter(|op| {
+                    op.account
+                        .as_ref()
+                        .map(|op_account| op_account.address == account.address)
+                        .unwrap_or_default()
+                })
+                .cloned()
+                .collect();
+            mismatches.push(Mismatch {
+                block_identifier: block_identifier.clone(),
+                account_identifier: account.clone(),
+                computed,
+                actual,
+                operations,
+            });
+        }
+        Ok(mismatches)
+    }
+
+    /// Compares the indexed height with the chain tip. The connector is only considered
+    /// synced when neither the node nor the indexer are catching up.
+    pub fn sync_status(
+        &self,
+        current_block: &BlockIdentifier,
+        syncing: bool,
+    ) -> Result<SyncStatus> {
+        let height = self.transaction_table.height()?;
+        let synced = !syncing && height >= current_block.index;
+        let stage = if syncing {
+            "node syncing"
+        } else if synced {
+            "synced"
+        } else {
+            "indexing"
+        };
+        Ok(SyncStatus {
+            current_index: Some(height as _),
+            target_index: Some(current_block.index as _),
+            stage: Some(stage.into()),
+            synced: Some(synced),
+        })
+    }
+
+    pub fn events(&self, req: &EventsBlocksRequest) -> Result<EventsBlocksResponse> {
+        let limit = std::cmp::min(req.limit.unwrap_or(100), 1000);
+        // an empty log is reported as -1, as 0 is the sequence of the first event
+        let Some(max_sequence) = self.event_table.max_sequence()? else {
+            return Ok(EventsBlocksResponse {
+                max_sequence: -1,
+                events: vec![],
+            });
+        };
+        // without an offset the last `limit` events are returned
+        let offset = req
+            .offset
+            .unwrap_or_else(|| (max_sequence + 1).saturating_sub(limit));
+        let events = self
+            .event_table
+            .range(offset, limit as _)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(EventsBlocksResponse {
+            max_sequence: max_sequence as _,
+            events,
+        })
+    }
+
+    /// Searches the indexed transactions up to `max_block`.
+    ///
+    /// The filter table is walked newest first for every filter of the request, the
+    /// walks are merged with the request operator and restricted to the transactions of
+    /// the account if one is given. Only the requested page is loaded from the block store.
+    /// The total count of a single index is kept in the count table, combined searches are
+    /// counted by merging the walks up to `max_block`.
+    pub async fn search(
+        &self,
+        req: &SearchTransactionsRequest,
+    ) -> Result<SearchTransactionsResponse> {
+        let height = self.transaction_table.height()?;
+        let max_block = req
+            .max_block
+            .map(u64::try_from)
+            .transpose()?
+            .unwrap_or(height);
+        let offset = usize::try_from(req.offset.unwrap_or_default())?;
+        let limit = usize::try_from(req.limit.unwrap_or(100))?.min(1000);
+        let operator = req.operator.unwrap_or(Operator::And);
+        let account = if let Some(account) = &req.account_identifier {
+            Some(account.clone())
+        } else {
+            req.address.as_ref().map(|address| AccountIdentifier {
+                address: address.clone(),
+                sub_account: None,
+                metadata: None,
+            })
+        };
+        let mut filters = vec![];
+        if let Some(ty) = req.r#type.as_ref() {
+            filters.push(Filter::Type(ty.clone()));
+        }
+        if let Some(status) = req.status.as_ref() {
+            filters.push(Filter::Status(status.clone()));
+        }
+        if let Some(currency) = req.currency.as_ref() {
+            filters.push(Filter::Currency(currency.clone()));
+        }
+        if let Some(coin) = req.coin_identifier.as_ref() {
+            filters.push(Filter::Coin(coin.clone()));
+        }
+        if let Some(success) = req.success {
+            filters.push(Filter::Success(success));
+        }
+
+        if let Some(tx) = req.transaction_identifier.as_ref() {
+            let mut transactions = vec![];
+            if let Some(tx) = self.transaction(tx).await? {
+                let statuses = self.client.operation_statuses();
+                let matched = Filter::from_transaction(&tx.transaction, &statuses);
+                let matches = match operator {
+                    _ if filters.is_empty() => true,
+                    Operator::And => filters.iter().all(|filter| matched.contains(filter)),
+                    Operator::Or => filters.iter().any(|filter| matched.contains(filter)),
+                };
+                if matches && tx.block_identifier.index <= max_block {
+                    transactions.push(tx);
+                }
+            }
+            return Ok(SearchTransactionsResponse {
+                total_count: transactions.len() as _,
+                transactions,
+                next_offset: None,
+                next_cursor: None,
+            });
+        }
+
+        // a cursor continues where the previous page ended, offsets are only returned for
+        // pages counted from the tip
+        let before = req.cursor.as_deref().map(decode_cursor).transpose()?;
+        let mut end = TransactionRef::new(max_block.saturating_add(1), 0);
+        if let Some(before) = before {
+            end = end.min(before);
+        }
+        let page = self
+            .matches(&filters, account.as_ref(), operator, end)
+            .skip(offset)
+            .take(limit + 1)
+            .collect::<Result<Vec<_>>>()?;
+        // searches answered by a single index read its count, otherwise the matches are
+        // counted by merging the indexes up to `max_block`
+        let total_count = match (account.as_ref(), filters.as_slice()) {
+            (None, []) => self.count(Index::All, max_block)?,
+            (Some(account), []) => self.count(Index::Account(account), max_block)?,
+            (None, [filter]) => self.count(Index::Filter(filter), max_block)?,
+            _ => {
+                let mut count = 0;
+                let before = TransactionRef::new(max_block.saturating_add(1), 0);
+                for tx in self.matches(&filters, account.as_ref(), operator, before) {
+                    tx?;
+                    count += 1;
+                }
+                count
+            }
+        };
+        let more = page.len() > limit;
+        let page = &page[..page.len().min(limit)];
+
+        let finalized_height = self.finalized_height()?;
+        let mut transactions = Vec::with_capacity(page.len());
+        let mut block: Option<Block> = None;
+        for tx in page {
+            let cached = block
+                .as_ref()
+                .map(|block| block.block_identifier.index == tx.block_index)
+                .unwrap_or_default();
+            if !cached {
+                block = Some(self.block_by_index(tx.block_index).await?);
+            }
+            let block = block.as_ref().unwrap();
+            if let Some(transaction) = block.transactions.get(tx.transaction_index as usize) {
+                transactions.push(block_transaction(
+                    &block.block_identifier,
+                    transaction.clone(),
+                    tx.block_index <= finalized_height,
+                ));
+            }
+        }
+        Ok(SearchTransactionsResponse {
+            transactions,
+            total_count: total_count as _,
+            next_offset: (more && before.is_none()).then_some((offset + limit) as _),
+            next_cursor: page.last().filter(|_| more).map(encode_cursor),
+        })
+    }
+
+    /// Returns the transactions matching the filters combined with `operator` and the
+    /// account, newest first, starting below `before`.
+    fn matches<'a>(
+        &'a self,
+        filters: &'a [Filter],
+        account: Option<&'a AccountIdentifier>,
+        operator: Operator,
+        before: TransactionRef,
+    ) -> Refs<'a> {
+        match (account, filters) {
+            (None, []) => self.refs(Index::All, before),
+            (Some(account), []) => self.refs(Index::Account(account), before),
+            (None, [filter]) => self.refs(Index::Filter(filter), before),
+            _ => {
+                let filters = filters
+                    .iter()
+                    .map(|filter| self.refs(Index::Filter(filter), before))
+                    .collect::<Vec<_>>();
+                let mut streams = vec![];
+                if !filters.is_empty() {
+                    streams.push(Merge::refs(filters, operator));
+                }
+                if let Some(account) = account {
+                    streams.push(self.refs(Index::Account(account), before));
+                }
+                Merge::refs(streams, Operator::And)
+            }
+        }
+    }
+
+    /// Returns the transactions of the index newest first, starting below `before`.
+    fn refs(&self, index: Index<'_>, before: TransactionRef) -> Refs<'_> {
+        match index {
+            Index::All => Box::new(self.block_table.range_rev(before.block_index).flat_map(
+                move |record| -> Refs<'_> {
+                    let (block_index, record) = match record {
+                        Ok(record) => record,
+                        Err(err) => return Box::new(std::iter::once(Err(err))),
+                    };
+                    let mut len = record.transactions.len() as u32;
+                    if block_index == before.block_index {
+                        len = len.min(before.transaction_index);
+                    }
+                    Box::new(
+                        (0..len)
+                            .rev()
+                            .map(move |index| Ok(TransactionRef::new(block_index, index))),
+                    )
+                },
+            )),
+            Index::Account(account) => Box::new(self.account_table.get_rev(account, Some(&before))),
+            Index::Filter(filter) => Box::new(self.filter_table.get_rev(filter, &before)),
+        }
+    }
+
+    /// Number of transactions of the index up to `max_block`.
+    fn count(&self, index: Index<'_>, max_block: u64) -> Result<u64> {
+        let count = self.count_table.get(&index)?;
+        if max_block >= self.transaction_table.height()? {
+            return Ok(count);
+        }
+        // the counts cover the whole index, the newer transactions are subtracted
+        let mut newer = 0;
+        for tx in self.refs(index, TransactionRef::new(u64::MAX, u32::MAX)) {
+            if tx?.block_index <= max_block {
+                break;
+            }
+            newer += 1;
+        }
+        Ok(count.saturating_sub(newer))
+    }
+}
+
+/// Returns the transaction of a search result, with a `finalized` flag in its metadata
+/// telling whether the block can still be reverted by a reorg.
+fn block_transaction(
+    block_identifier: &BlockIdentifier,
+    mut transaction: Transaction,
+    finalized: bool,
+) -> BlockTransaction {
+    let metadata = transaction
+        .metadata
+        .get_or_insert_with(|| serde_json::Value::Object(Default::default()));
+    if let Some(metadata) = metadata.as_object_mut() {
+        metadata.insert("finalized".into(), finalized.into());
+    }
+    BlockTransaction::new(block_identifier.clone(), transaction)
+}
+
+/// Cursors are the position of the last transaction of a page, so that they stay valid
+/// while new blocks are indexed.
+fn encode_cursor(tx: &TransactionRef) -> String {
+    hex::encode(tx.to_bytes())
+}
+
+/// Decodes a cursor returned by [`encode_cursor`].
+pub(crate) fn decode_cursor(cursor: &str) -> Result<TransactionRef> {
+    let bytes = hex::decode(cursor)?;
+    anyhow::ensure!(bytes.len() == 12, "invalid cursor");
+    Ok(TransactionRef::from_bytes(&bytes))
+}
+
+/// Whether the status of the operation is published as unsuccessful.
+fn is_failed(op: &Operation, statuses: &[OperationStatus]) -> bool {
+    statuses
+        .iter()
+        .any(|status| Some(&status.status) == op.status.as_ref() && !status.successful)
+}
+
+/// A transaction is successful unless one of its operations failed.
+fn is_success(tx: &Transaction, statuses: &[OperationStatus]) -> bool {
+    !tx.operations.iter().any(|op| is_failed(op, statuses))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::crypto::address::AddressFormat;
+    use crate::crypto::{Algorithm, PublicKey, SecretKey, Signature};
+    use crate::storage::MemoryStorage;
+    use crate::types::{
+        BalanceExemption, CallRequest, Coin, OperationIdentifier, Peer, SubAccountIdentifier,
+    };
+    use crate::{BlockchainConfig, ParsedTransaction, TransactionBuilder, UnsignedTransaction};
+    use async_trait::async_trait;
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone)]
+    pub struct MockClient {
+        config: BlockchainConfig,
+        genesis: BlockIdentifier,
+        chain: Arc<Mutex<Vec<Block>>>,
+        finalized: Arc<Mutex<u64>>,
+    }
+
+    impl MockClient {
+        pub fn new() -> Self {
+            let config = BlockchainConfig {
+                blockchain: "mock",
+                network: "dev",
+                algorithm: Algorithm::EcdsaRecoverableSecp256k1,
+                address_format: AddressFormat::Eip55,
+                coin: 1,
+                bip44: true,
+                utxo: false,
+                historical_balance_lookup: true,
+                currency_unit: "unit",
+                currency_symbol: "MOCK",
+                currency_decimals: 0,
+                node_port: 0,
+                node_image: "mock:1.0.0",
+                node_command: Arc::new(|_, _| vec![]),
+                node_additional_ports: &[],
+                connector_port: 0,
+                testnet: true,
+            };
+            let genesis = mock_block(0, 0, &[]);
+            Self {
+                config,
+                genesis: genesis.block_identifier.clone(),
+                chain: Arc::new(Mutex::new(vec![genesis])),
+                finalized: Default::default(),
+            }
+        }
+
+        pub fn finalize(&self, index: u64) {
+            *self.finalized.lock().unwrap() = index;
+        }
+
+        /// Replaces the chain above `fork_index` with `len` blocks of the given `fork`.
+        pub fn extend(&self, fork_index: u64, fork: u8, len: u64, accounts: &[&str]) {
+            let mut chain = self.chain.lock().unwrap();
+            chain.truncate(fork_index as usize + 1);
+            for index in fork_index + 1..fork_index + 1 + len {
+                let mut block = mock_block(index, fork, accounts);
+                block.parent_block_identifier = chain.last().unwrap().block_identifier.clone();
+                chain.push(block);
+            }
+        }
+    }
+
+    fn mock_currency() -> Currency {
+        Currency {
+            symbol: "MOCK".into(),
+            decimals: 0,
+            metadata: None,
+        }
+    }
+
+    fn mock_block(index: u64, fork: u8, accounts: &[&str]) -> Block {
+        let hash = format!("{:02x}{:016x}", fork, index);
+        let transactions = accounts
+            .iter()
+            .enumerate()
+            .map(|(i, address)| Transaction {
+                transaction_identifier: TransactionIdentifier::new(format!("{}{:08x}", hash, i)),
+                operations: vec![Operation {
+                    operation_identifier: OperationIdentifier::new(0),
+                    r#type: "TRANSFER".into(),
+                    account: Some(AccountIdentifier::new(address.to_string())),
+                    amount: Some(Amount::new("10".into(), mock_currency())),
+                    ..Default::default()
+                }],
+                related_transactions: None,
+                metadata: None,
+            })
+            .collect();
+        Block {
+            block_identifier: BlockIdentifier::new(index, hash.clone()),
+            parent_block_identifier: BlockIdentifier::new(index.saturating_sub(1), hash),
+            timestamp: 0,
+            transactions,
+            metadata: None,
+        }
+    }
+
+    #[derive(Default)]
+    pub struct MockTransactionBuilder;
+
+    impl TransactionBuilder for MockTransactionBuilder {
+        type MetadataParams = ();
+        type Metadata = ();
+
+        fn transfer(&self, _address: &Address, _amount: u128) -> Result<()> {
+            Ok(())
+        }
+
+        fn method_call(
+            &self,
+            _contract: &str,
+            _method: &str,
+            _values: &[String],
+            _amount: u128,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        fn deploy_contract(&self, _contract_binary: Vec<u8>) -> Result<()> {
+            Ok(())
+        }
+
+        fn create_unsigned(
+            &self,
+            _config: &BlockchainConfig,
+            _metadata_params: &(),
+            _metadata: &(),
+            public_key: &PublicKey,
+        ) -> Result<UnsignedTransaction> {
+            Ok(UnsignedTransaction {
+                transaction: public_key.to_bytes(),
+                signing_payload: vec![],
+            })
+        }
+
+        fn combine(
+            &self,
+            _config: &BlockchainConfig,
+            transaction: &[u8],
+            signature: &Signature,
+        ) -> Result<Vec<u8>> {
+            Ok([transaction, &signature.to_bytes()].concat())
+        }
+
+        fn create_and_sign(
+            &self,
+            config: &BlockchainConfig,
+            metadata_params: &(),
+            metadata: &(),
+            secret_key: &SecretKey,
+        ) -> Result<Vec<u8>> {
+            let unsigned =
+                self.create_unsigned(config, metadata_params, metadata, &secret_key.public_key())?;
+            let signature = secret_key.sign(&unsigned.signing_payload, "");
+            self.combine(config, &unsigned.transaction, &signature)
+        }
+
+        fn parse(
+            &self,
+            _config: &BlockchainConfig,
+            _transaction: &[u8],
+            signed: bool,
+        ) -> Result<ParsedTransaction> {
+            Ok(ParsedTransaction {
+                operations: vec![],
+                signers: vec![],
+                signed,
+                metadata: None,
+            })
+        }
+
+        fn hash(&self, _config: &BlockchainConfig, transaction: &[u8]) -> Result<Vec<u8>> {
+            Ok(transaction.to_vec())
+        }
+    }
+
+    #[async_trait]
+    impl BlockchainClient for MockClient {
+        type MetadataParams = ();
+        type Metadata = ();
+        type TransactionBuilder = MockTransactionBuilder;
+
+        fn create_config(_network: &str) -> Result<BlockchainConfig> {
+            Ok(Self::new().config)
+        }
+
+        async fn new(_network: &str, _addr: &str) -> Result<Self> {
+            Ok(Self::new())
+        }
+
+        fn config(&self) -> &BlockchainConfig {
+            &self.config
+        }
+
+        fn genesis_block(&self) -> &BlockIdentifier {
+            &self.genesis
+        }
+
+        fn operation_statuses(&self) -> Vec<OperationStatus> {
+            vec![
+                OperationStatus {
+                    status: "SUCCESS".into(),
+                    successful: true,
+                },
+                OperationStatus {
+                    status: "FAILURE".into(),
+                    successful: false,
+                },
+            ]
+        }
+
+        fn operation_types(&self) -> Vec<String> {
+            vec![]
+        }
+
+        fn call_methods(&self) -> Vec<String> {
+            vec![]
+        }
+
+        fn balance_exemptions(&self) -> Vec<BalanceExemption> {
+            vec![]
+        }
+
+        async fn node_version(&self) -> Result<String> {
+            Ok("mock".into())
+        }
+
+        async fn current_block(&self) -> Result<BlockIdentifier> {
+            let chain = self.chain.lock().unwrap();
+            Ok(chain.last().unwrap().block_identifier.clone())
+        }
+
+        async fn finalized_block(&self) -> Result<BlockIdentifier> {
+            let chain = self.chain.lock().unwrap();
+            let index = std::cmp::min(*self.finalized.lock().unwrap(), chain.len() as u64 - 1);
+            Ok(chain[index as usize].block_identifier.clone())
+        }
+
+        async fn block_identifier(
+            &self,
+            block: &PartialBlockIdentifier,
+        ) -> Result<BlockIdentifier> {
+            Ok(self.block(block).await?.block_identifier)
+        }
+
+        async fn balance(&self, address: &Address, block: &BlockIdentifier) -> Result<u128> {
+            let chain = self.chain.lock().unwrap();
+            let mut balance = 0;
+            for block in chain.iter().take(block.index as usize + 1) {
+                for op in block.transactions.iter().flat_map(|tx| &tx.operations) {
+                    if let (Some(account), Some(amount)) = (&op.account, &op.amount) {
+                        if account.address == address.address() {
+                            balance += amount.value.parse::<u128>()?;
+                        }
+                    }
+                }
+            }
+            Ok(balance)
+        }
+
+        async fn coins(&self, _address: &Address, _block: &BlockIdentifier) -> Result<Vec<Coin>> {
+            anyhow::bail!("not a utxo chain");
+        }
+
+        async fn faucet(&self, _address: &Address, _param: u128) -> Result<Vec<u8>> {
+            anyhow::bail!("no faucet");
+        }
+
+        async fn metadata(&self, _public_key: &PublicKey, _params: &()) -> Result<()> {
+            Ok(())
+        }
+
+        async fn submit(&self, _transaction: &[u8]) -> Result<Vec<u8>> {
+            anyhow::bail!("read only");
+        }
+
+        async fn block(&self, block: &PartialBlockIdentifier) -> Result<Block> {
+            let chain = self.chain.lock().unwrap();
+            let block = match (block.index, block.hash.as_ref()) {
+                (Some(index), _) => chain.get(index as usize),
+                (None, Some(hash)) => chain.iter().find(|b| &b.block_identifier.hash == hash),
+                (None, None) => chain.last(),
+            };
+            block
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("block not found"))
+        }
+
+        async fn block_transaction(
+            &self,
+            block: &BlockIdentifier,
+            tx: &TransactionIdentifier,
+        ) -> Result<Transaction> {
+            let block = self
+                .block(&PartialBlockIdentifier {
+                    index: Some(block.index),
+                    hash: None,
+                })
+                .await?;
+            block
+                .transactions
+                .into_iter()
+                .find(|t| &t.transaction_identifier == tx)
+                .ok_or_else(|| anyhow::anyhow!("transaction not found"))
+        }
+
+        async fn block_timestamp(&self, block: &BlockIdentifier) -> Result<i64> {
+            Ok(block.index as i64 * 1000)
+        }
+
+        async fn peers(&self) -> Result<Vec<Peer>> {
+            Ok(vec![])
+        }
+
+        async fn syncing(&self) -> Result<bool> {
+            Ok(false)
+        }
+
+        async fn mempool(&self) -> Result<Vec<TransactionIdentifier>> {
+            Ok(vec![])
+        }
+
+        async fn mempool_transaction(&self, _tx: &TransactionIdentifier) -> Result<Transaction> {
+            anyhow::bail!("transaction not found");
+        }
+
+        async fn call(&self, _req: &CallRequest) -> Result<Value> {
+            anyhow::bail!("unsupported");
+        }
+    }
+
+    pub fn mock_indexer() -> Result<(MockClient, Indexer<MockClient>)> {
+        let client = MockClient::new();
+        let indexer = Indexer::new(Arc::new(MemoryStorage::default()), client.clone());
+        indexer.migrate()?;
+        Ok((client, indexer))
+    }
+
+    fn account(address: &str) -> AccountIdentifier {
+        AccountIdentifier::new(address.into())
+    }
+
+    #[tokio::test]
+    async fn test_sync_reorg() -> Result<()> {
+        let (client, indexer) = mock_indexer()?;
+        client.extend(0, 0, 3, &["alice"]);
+        let events = indexer.sync().await?;
+        assert_eq!(events.len(), 3);
+        assert!(events.iter().all(|e| e.r#type == BlockEventType::Added));
+        assert_eq!(
+            indexer
+                .account_table
+                .get_rev(&account("alice"), None)
+                .count(),
+            3
+        );
+
+        client.extend(1, 1, 3, &["bob"]);
+        let events = indexer
+            .sync()
+            .await?
+            .into_iter()
+            .map(|e| (e.r#type, e.block_identifier.index))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                (BlockEventType::Removed, 3),
+                (BlockEventType::Removed, 2),
+                (BlockEventType::Added, 2),
+                (BlockEventType::Added, 3),
+                (BlockEventType::Added, 4),
+            ]
+        );
+        assert_eq!(indexer.transaction_table.height()?, 4);
+        assert_eq!(
+            indexer
+                .account_table
+                .get_rev(&account("alice"), None)
+                .count(),
+            1
+        );
+        assert_eq!(
+            indexer.account_table.get_rev(&account("bob"), None).count(),
+            3
+        );
+        let orphaned = TransactionIdentifier::new(format!("{:02x}{:016x}{:08x}", 0, 2, 0));
+        assert!(indexer.transaction_table.get(&orphaned)?.is_none());
+        let count = |address| indexer.count_table.get(&Index::Account(&account(address)));
+        assert_eq!((count("alice")?, count("bob")?), (1, 3));
+        assert_eq!(indexer.count_table.get(&Index::All)?, 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_concurrent() -> Result<()> {
+        let (client, indexer) = mock_indexer()?;
+        let indexer = indexer.with_sync_concurrency(4);
+        client.extend(0, 0, 10, &["alice"]);
+        let events = indexer.sync().await?;
+        let indices = events
+            .iter()
+            .map(|e| e.block_identifier.index)
+            .collect::<Vec<_>>();
+        assert_eq!(indices, (1..=10).collect::<Vec<_>>());
+        assert_eq!(
+            indexer
+                .account_table
+                .get_rev(&account("alice"), None)
+                .count(),
+            10
+        );
+
+        client.extend(5, 1, 7, &["bob"]);
+        let events = indexer.sync().await?;
+        assert_eq!(events.len(), 5 + 7);
+        assert_eq!(indexer.transaction_table.height()?, 12);
+        assert_eq!(
+            indexer
+                .account_table
+                .get_rev(&account("alice"), None)
+                .count(),
+            5
+        );
+        assert_eq!(
+            indexer.account_table.get_rev(&account("bob"), None).count(),
+            7
+        );
+        let sequences = events.iter().map(|e| e.sequence).collect::<Vec<_>>();
+        assert_eq!(sequences, (10..22).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_block_store() -> Result<()> {
+        let (client, indexer) = mock_indexer()?;
+        client.extend(0, 0, 3, &["alice"]);
+        indexer.sync().await?;
+        let expected = client.block(&PartialBlockIdentifier::default()).await?;
+        // blocks are served without the node once they are indexed
+        client.extend(0, 0, 0, &[]);
+
+        let block = indexer
+            .block(&PartialBlockIdentifier {
+                index: None,
+                hash: Some(expected.block_identifier.hash.clone()),
+            })
+            .await?;
+        assert_eq!(block, expected);
+        let tx = &expected.transactions[0];
+        let transaction = indexer
+            .block_transaction(&expected.block_identifier, &tx.transaction_identifier)
+            .await?;
+        assert_eq!(&transaction, tx);
+        let mut req = SearchTransactionsRequest::new(client.config().network());
+        req.address = Some("alice".into());
+        assert_eq!(indexer.search(&req).await?.transactions.len(), 3);
+
+        client.extend(0, 1, 4, &["bob"]);
+        indexer.sync().await?;
+        assert!(indexer
+            .block_store
+            .index(&expected.block_identifier.hash)?
+            .is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_balance_history() -> Result<()> {
+        let (client, indexer) = mock_indexer()?;
+        client.extend(0, 0, 3, &["alice"]);
+        indexer.sync().await?;
+        let alice = account("alice");
+        assert_eq!(indexer.balance_at(&alice, &mock_currency(), 2)?, Some(20));
+        assert_eq!(indexer.balance_at(&alice, &mock_currency(), 4)?, None);
+
+        let mut req = AccountBalanceHistoryRequest::new(client.config().network(), alice.clone());
+        req.limit = Some(2);
+        let res = indexer.balance_history(&req)?;
+        let balances = res
+            .balances
+            .iter()
+            .map(|change| change.balance.value.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(balances, ["10", "20"]);
+        assert_eq!(res.next_offset, Some(2));
+        req.offset = res.next_offset;
+        let res = indexer.balance_history(&req)?;
+        assert_eq!(res.balances.len(), 1);
+        assert_eq!(res.balances[0].delta.value, "10");
+        assert_eq!(res.next_offset, None);
+
+        client.extend(1, 1, 3, &["bob"]);
+        indexer.sync().await?;
+        assert_eq!(indexer.balance_at(&alice, &mock_currency(), 4)?, Some(10));
+        let bob = account("bob");
+        assert_eq!(indexer.balance_at(&bob, &mock_currency(), 4)?, Some(30));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_balance_sub_account() -> Result<()> {
+        let (client, indexer) = mock_indexer()?;
+        client.extend(0, 0, 2, &["alice", "alice"]);
+        let bonded = AccountIdentifier {
+            address: "alice".into(),
+            sub_account: Some(SubAccountIdentifier {
+                address: "bonded".into(),
+                metadata: None,
+            }),
+            metadata: None,
+        };
+        client.chain.lock().unwrap()[2].transactions[1].operations[0].account =
+            Some(bonded.clone());
+        indexer.sync().await?;
+        let alice = account("alice");
+        assert_eq!(indexer.balance_at(&alice, &mock_currency(), 2)?, Some(30));
+        assert_eq!(indexer.balance_at(&bonded, &mock_currency(), 2)?, Some(10));
+        let req = AccountBalanceHistoryRequest::new(client.config().network(), alice);
+        assert_eq!(indexer.balance_history(&req)?.balances.len(), 2);
+
+        client.extend(2, 0, 1, &["alice"]);
+        client.chain.lock().unwrap()[3].transactions[0].operations[0].amount =
+            Some(Amount::new("1.5".into(), mock_currency()));
+        assert!(indexer.sync().await.is_err());
+        assert_eq!(indexer.height()?, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search() -> Result<()> {
+        let (client, indexer) = mock_indexer()?;
+        client.extend(0, 0, 3, &["alice", "bob"]);
+        client.chain.lock().unwrap()[3].transactions[1].operations[0].status =
+            Some("FAILURE".into());
+        indexer.sync().await?;
+        let search = |req: SearchTransactionsRequest| {
+            let indexer = &indexer;
+            async move { indexer.search(&req).await }
+        };
+        let request = || SearchTransactionsRequest::new(client.config().network());
+
+        let res = search(request()).await?;
+        assert_eq!(res.total_count, 6);
+        assert_eq!(res.next_offset, None);
+
+        let mut req = request();
+        req.r#type = Some("TRANSFER".into());
+        req.currency = Some(mock_currency());
+        req.limit = Some(4);
+        let res = search(req.clone()).await?;
+        assert_eq!(res.transactions.len(), 4);
+        assert_eq!(res.total_count, 6);
+        assert_eq!(res.next_offset, Some(4));
+        req.offset = res.next_offset;
+        let res = search(req).await?;
+        assert_eq!(res.transactions.len(), 2);
+        assert_eq!(res.total_count, 6);
+
+        let mut req = request();
+        req.address = Some("bob".into());
+        req.max_block = Some(2);
+        assert_eq!(search(req).await?.total_count, 2);
+
+        let mut req = request();
+        req.r#type = Some("STAKE".into());
+        req.currency = Some(mock_currency());
+        assert_eq!(search(req.clone()).await?.total_count, 0);
+        req.operator = Some(Operator::Or);
+        assert_eq!(search(req).await?.total_count, 6);
+
+        let mut req = request();
+        req.success = Some(false);
+        let res = search(req.clone()).await?;
+        assert_eq!(res.total_count, 1);
+        assert_eq!(res.transactions[0].block_identifier.index, 3);
+        req.transaction_identifier = Some(
+            res.transactions[0]
+                .transaction
+                .transaction_identifier
+                .clone(),
+        );
+        assert_eq!(search(req).await?.total_count, 1);
+
+        let mut req = request();
+        req.address = Some("alice".into());
+        req.success = Some(true);
+        req.max_block = Some(2);
+        req.limit = Some(1);
+        req.offset = Some(1);
+        let res = search(req.clone()).await?;
+        assert_eq!(res.total_count, 2);
+        assert_eq!(res.transactions[0].block_identifier.index, 1);
+        assert_eq!(res.next_offset, None);
+        req.max_block = None;
+        req.offset = None;
+        let res = search(req).await?;
+        assert_eq!(res.transactions.len(), 1);
+        assert_eq!(res.total_count, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_cursor() -> Result<()> {
+        let (client, indexer) = mock_indexer()?;
+        client.extend(0, 0, 3, &["alice", "alice2"]);
+        indexer.sync().await?;
+        let mut req = SearchTransactionsRequest::new(client.config().network());
+        req.address = Some("alice".into());
+        req.limit = Some(2);
+
+        let res = indexer.search(&req).await?;
+        assert_eq!(res.total_count, 3);
+        let blocks = res
+            .transactions
+            .iter()
+            .map(|tx| tx.block_identifier.index)
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, vec![3, 2]);
+        assert!(res.next_cursor.is_some());
+
+        // new blocks don't shift the next page
+        client.extend(3, 0, 2, &["alice"]);
+        indexer.sync().await?;
+        req.cursor = res.next_cursor;
+        let res = indexer.search(&req).await?;
+        assert_eq!(res.total_count, 5);
+        assert_eq!(res.transactions.len(), 1);
+        assert_eq!(res.transactions[0].block_identifier.index, 1);
+        assert_eq!(res.next_cursor, None);
+
+        // offsets are only returned for pages counted from the tip
+        req.limit = Some(1);
+        req.cursor = Some(encode_cursor(&TransactionRef::new(4, 0)));
+        let res = indexer.search(&req).await?;
+        assert_eq!(res.next_offset, None);
+        assert!(res.next_cursor.is_some());
+        req.limit = Some(2);
+
+        req.r#type = Some("TRANSFER".into());
+        req.cursor = Some(encode_cursor(&TransactionRef::new(4, 0)));
+        let res = indexer.search(&req).await?;
+        let blocks = res
+            .transactions
+            .iter()
+            .map(|tx| tx.block_identifier.index)
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, vec![3, 2]);
+
+        req.cursor = Some("zz".into());
+        assert!(indexer.search(&req).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_finalized() -> Result<()> {
+        let (client, indexer) = mock_indexer()?;
+        client.extend(0, 0, 3, &["alice"]);
+        client.finalize(1);
+        indexer.sync().await?;
+        assert_eq!(indexer.finalized_height()?, 1);
+        let mut req = SearchTransactionsRequest::new(client.config().network());
+        req.address = Some("alice".into());
+        let finalized = indexer
+            .search(&req)
+            .await?
+            .transactions
+            .iter()
+            .map(|tx| tx.transaction.metadata.as_ref().unwrap()["finalized"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(finalized, vec![false, false, true]);
+
+        let indexer = Indexer::new(Arc::new(MemoryStorage::default()), client.clone())
+            .with_finalized_only(true);
+        indexer.sync().await?;
+        assert_eq!(indexer.height()?, 1);
+        client.finalize(3);
+        indexer.sync().await?;
+        assert_eq!(indexer.height()?, 3);
+        assert_eq!(indexer.finalized_height()?, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reconcile() -> Result<()> {
+        let (client, indexer) = mock_indexer()?;
+        client.extend(0, 0, 3, &["alice", "bob"]);
+        indexer.sync().await?;
+        let accounts = indexer.reconciliation_accounts(2, &[account("carol")], 1)?;
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0], account("carol"));
+        let accounts = vec![account("alice"), account("bob"), account("carol")];
+        assert!(indexer.reconcile(2, &accounts).await?.is_empty());
+
+        // the node credits alice with more than the operations of the block add up to
+        client.chain.lock().unwrap()[2].transactions[0].operations[0]
+            .amount
+            .as_mut()
+            .unwrap()
+            .value = "20".into();
+        let mismatches = indexer.reconcile(2, &accounts).await?;
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].account_identifier, account("alice"));
+        assert_eq!(mismatches[0].block_identifier.index, 2);
+        assert_eq!((mismatches[0].computed, mismatches[0].actual), (10, 20));
+        assert_eq!(mismatches[0].operations.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_events() -> Result<()> {
+        let (client, indexer) = mock_indexer()?;
+        let mut req = EventsBlocksRequest::new(client.config().network());
+        let res = indexer.events(&req)?;
+        assert_eq!((res.max_sequence, res.events.len()), (-1, 0));
+
+        client.extend(0, 0, 3, &[]);
+        indexer.sync().await?;
+        client.extend(2, 1, 2, &[]);
+        indexer.sync().await?;
+
+        let res = indexer.events(&req)?;
+        assert_eq!(res.max_sequence, 5);
+        let sequences = res.events.iter().map(|e| e.sequence).collect::<Vec<_>>();
+        assert_eq!(sequences, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(res.events[3].r#type, BlockEventType::Removed);
+        assert_eq!(res.events[3].block_identifier.index, 3);
+
+        req.offset = Some(2);
+        req.limit = Some(2);
+        let res = indexer.events(&req)?;
+        let sequences = res.events.iter().map(|e| e.sequence).collect::<Vec<_>>();
+        assert_eq!(sequences, vec![2, 3]);
+
+        req.offset = None;
+        let res = indexer.events(&req)?;
+        let sequences = res.events.iter().map(|e| e.sequence).collect::<Vec<_>>();
+        assert_eq!(sequences, vec![4, 5]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_status() -> Result<()> {
+        let (client, indexer) = mock_indexer()?;
+        client.extend(0, 0, 3, &[]);
+        let current = client.current_block().await?;
+
+        let status = indexer.sync_status(&current, false)?;
+        assert_eq!(status.current_index, Some(0));
+        assert_eq!(status.target_index, Some(3));
+        assert_eq!(status.synced, Some(false));
+
+        indexer.sync().await?;
+        let status = indexer.sync_status(&current, false)?;
+        assert_eq!(status.current_index, Some(3));
+        assert_eq!(status.synced, Some(true));
+
+        let status = indexer.sync_status(&current, true)?;
+        assert_eq!(status.synced, Some(false));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_migrate() -> Result<()> {
+        let (client, indexer) = mock_indexer()?;
+        assert_eq!(indexer.meta_table.schema_version()?, Some(SCHEMA_VERSION));
+
+        let storage = Arc::new(MemoryStorage::default());
+        let unversioned = Indexer::new(storage.clone(), client.clone());
+        client.extend(0, 0, 3, &["alice"]);
+        unversioned.sync().await?;
+        unversioned.migrate()?;
+        assert_eq!(unversioned.height()?, 0);
+        assert_eq!(
+            unversioned.meta_table.schema_version()?,
+            Some(SCHEMA_VERSION)
+        );
+        unversioned.sync().await?;
+        assert_eq!(unversioned.height()?, 3);
+
+        let mut batch = Batch::default();
+        indexer
+            .meta_table
+            .set_schema_version(&mut batch, SCHEMA_VERSION + 1);
+        indexer.storage.commit(batch)?;
+        assert!(indexer.migrate().is_err());
+
+        // indexes of version 1 are counted
+        let mut batch = Batch::default();
+        unversioned.meta_table.set_schema_version(&mut batch, 1);
+        unversioned.storage.commit(batch)?;
+        unversioned.storage.clear(Table::Counts)?;
+        unversioned.migrate()?;
+        let req = SearchTransactionsRequest::new(client.config().network());
+        assert_eq!(unversioned.search(&req).await?.total_count, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reindex() -> Result<()> {
+        let (client, indexer) = mock_indexer()?;
+        client.extend(0, 0, 3, &["alice"]);
+        indexer.sync().await?;
+        let events = indexer.reindex_from(2)?;
+        assert_eq!(events.len(), 2);
+        assert!(events
+            .iter()
+            .all(|event| event.r#type == BlockEventType::Removed));
+        assert_eq!(indexer.height()?, 1);
+        assert_eq!(indexer.sync().await?.len(), 2);
+        assert_eq!(indexer.height()?, 3);
+        assert!(indexer.reindex_from(0).is_err());
+
+        // restarting with the same height doesn't reindex again
+        assert!(indexer.reindex_from(2)?.is_empty());
+        assert_eq!(indexer.height()?, 3);
+        assert_eq!(indexer.reindex_from(3)?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_height() -> Result<()> {
+        let client = MockClient::new();
+        client.extend(0, 0, 5, &["alice"]);
+        let mut req = SearchTransactionsRequest::new(client.config().network());
+        req.address = Some("alice".into());
+
+        let indexer = Indexer::new(Arc::new(MemoryStorage::default()), client.clone())
+            .with_start_height(StartHeight::Height(3));
+        indexer.migrate()?;
+        indexer.sync().await?;
+        assert_eq!(indexer.height()?, 5);
+        assert_eq!(indexer.oldest_block()?.map(|block| block.index), Some(3));
+        assert_eq!(indexer.search(&req).await?.total_count, 3);
+        assert_eq!(
+            indexer.balance_at(&account("alice"), &mock_currency(), 4)?,
+            None
+        );
+        let all = SearchTransactionsRequest::new(client.config().network());
+        assert_eq!(indexer.search(&all).await?.transactions.len(), 3);
+
+        let indexer = Indexer::new(Arc::new(MemoryStorage::default()), client.clone())
+            .with_start_height(StartHeight::Depth(1));
+        indexer.migrate()?;
+        indexer.sync().await?;
+        assert_eq!(indexer.oldest_block()?.map(|block| block.index), Some(4));
+        assert_eq!(indexer.search(&req).await?.total_count, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retention() -> Result<()> {
+        let client = MockClient::new();
+        let indexer = Indexer::new(Arc::new(MemoryStorage::default()), client.clone())
+            .with_retention(Some(2));
+        indexer.migrate()?;
+        client.extend(0, 0, 2, &["alice", "bob"]);
+        client.extend(2, 0, 3, &["alice"]);
+        indexer.sync().await?;
+        assert_eq!(indexer.oldest_block()?.map(|block| block.index), Some(4));
+
+        let mut req = SearchTransactionsRequest::new(client.config().network());
+        req.address = Some("alice".into());
+        assert_eq!(indexer.search(&req).await?.total_count, 2);
+        req.address = Some("bob".into());
+        assert_eq!(indexer.search(&req).await?.total_count, 0);
+        let all = SearchTransactionsRequest::new(client.config().network());
+        let res = indexer.search(&all).await?;
+        let blocks = res
+            .transactions
+            .iter()
+            .map(|tx| tx.block_identifier.index)
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, [5, 4]);
+
+        let (alice, bob) = (account("alice"), account("bob"));
+        assert_eq!(indexer.balance_at(&alice, &mock_currency(), 3)?, None);
+        assert_eq!(indexer.balance_at(&alice, &mock_currency(), 4)?, Some(40));
+        assert_eq!(indexer.balance_at(&bob, &mock_currency(), 5)?, Some(20));
+        let rows = |account| indexer.balance_table.history(account, None).count();
+        assert_eq!((rows(&alice), rows(&bob)), (2, 1));
+
+        // pruned blocks can't be reindexed
+        assert!(indexer.reindex_from(3).is_err());
+        assert_eq!(indexer.reindex_from(4)?.len(), 2);
+        assert_eq!(indexer.oldest_block()?.map(|block| block.index), None);
+        Ok(())
     }
 }
@@ -1,47 +1,161 @@
-use crate::indexer::Indexer;
+use crate::indexer::{decode_cursor, Indexer, StartHeight};
+use crate::metrics::{MeteredClient, Metrics, MetricsMiddleware};
+use crate::storage::Backend;
 use anyhow::Result;
 use clap::Parser;
-use rosetta_core::crypto::address::Address;
-use rosetta_core::crypto::PublicKey;
+use futures::StreamExt;
+use rosetta_core::crypto::address::{Address, AddressFormat, Ss58AddressFormat};
+use rosetta_core::crypto::{PublicKey, Signature};
 use rosetta_core::types::{
-    AccountBalanceRequest, AccountBalanceResponse, AccountCoinsRequest, AccountCoinsResponse,
-    AccountFaucetRequest, Amount, BlockRequest, BlockResponse, BlockTransactionRequest,
-    BlockTransactionResponse, CallRequest, CallResponse, ConstructionMetadataRequest,
-    ConstructionMetadataResponse, ConstructionSubmitRequest, MetadataRequest, NetworkIdentifier,
-    NetworkListResponse, NetworkOptionsResponse, NetworkRequest, NetworkStatusResponse,
-    SearchTransactionsRequest, TransactionIdentifier, TransactionIdentifierResponse, Version,
+    AccountBalanceHistoryRequest, AccountBalanceRequest, AccountBalanceResponse,
+    AccountCoinsRequest, AccountCoinsResponse, AccountFaucetRequest, AccountIdentifier, Allow,
+    Amount, BlockEvent, BlockEventType, BlockRequest, BlockResponse, BlockTransactionRequest,
+    BlockTransactionResponse, CallRequest, CallResponse, ConstructionCombineRequest,
+    ConstructionCombineResponse, ConstructionDeriveRequest, ConstructionDeriveResponse,
+    ConstructionHashRequest, ConstructionMetadataRequest, ConstructionMetadataResponse,
+    ConstructionParseRequest, ConstructionParseResponse, ConstructionPayloadsRequest,
+    ConstructionPayloadsResponse, ConstructionPreprocessRequest, ConstructionPreprocessResponse,
+    ConstructionSubmitRequest, EventsBlocksRequest, MempoolResponse, MempoolTransactionRequest,
+    MempoolTransactionResponse, MetadataRequest, NetworkIdentifier, NetworkListResponse,
+    NetworkOptionsResponse, NetworkRequest, NetworkStatusResponse, Operation,
+    SearchTransactionsRequest, SigningPayload, TransactionIdentifier,
+    TransactionIdentifierResponse, Version,
 };
+use serde::Deserialize;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tide::http::headers::HeaderValue;
 use tide::security::{CorsMiddleware, Origin};
+use tide::utils::After;
 use tide::{Body, Request, Response};
 
 pub use rosetta_core::*;
 
+mod error;
 mod indexer;
+mod metrics;
+mod snapshot;
+mod storage;
+
+pub use crate::error::Error;
 
 #[derive(Parser)]
 struct Opts {
     #[clap(long)]
     network: String,
+    #[clap(long, required_unless_present_any = ["export_snapshot", "import_snapshot"])]
+    addr: Option<SocketAddr>,
+    #[clap(long, required_unless_present = "offline")]
+    node_addr: Option<String>,
+    #[clap(long, required_unless_present = "offline")]
+    path: Option<PathBuf>,
+    /// Storage backend of the indexer.
+    #[clap(long, value_enum, default_value_t = Backend::Sled)]
+    backend: Backend,
+    /// Seconds between syncs when the node doesn't announce new heads.
+    #[clap(long, default_value_t = 10)]
+    poll_interval: u64,
+    /// Only indexes blocks that can no longer be reverted by a reorg.
+    #[clap(long)]
+    finalized_only: bool,
+    /// First block indexed by an empty index, balance history starts at zero at this block.
+    #[clap(long, conflicts_with = "start_depth")]
+    start_height: Option<u64>,
+    /// Starts an empty index this many blocks below the tip instead of at genesis.
+    #[clap(long)]
+    start_depth: Option<u64>,
+    /// Number of recent blocks kept in the index, older blocks are pruned.
     #[clap(long)]
-    addr: SocketAddr,
+    retention: Option<u64>,
+    /// Number of blocks fetched in parallel while the indexer catches up with the node.
+    #[clap(long, default_value_t = 8)]
+    sync_concurrency: usize,
+    /// Accounts whose balance changes are reconciled with the node after every indexed block.
+    #[clap(long, value_delimiter = ',')]
+    reconcile_accounts: Vec<String>,
+    /// Number of accounts with a balance change that are reconciled per indexed block.
+    #[clap(long, default_value_t = 0)]
+    reconcile_sample: usize,
+    /// Serves the construction endpoints that don't require a node without connecting to one.
     #[clap(long)]
-    node_addr: String,
+    offline: bool,
+    /// Writes a snapshot of the index at `--path` to the file and exits. The server must
+    /// not be running.
+    #[clap(long, conflicts_with = "import_snapshot")]
+    export_snapshot: Option<PathBuf>,
+    /// Loads a snapshot into the empty index at `--path` and exits.
     #[clap(long)]
-    path: PathBuf,
+    import_snapshot: Option<PathBuf>,
+    /// Drops the indexed blocks from this height on startup, so that they are indexed again.
+    ///
+    /// Only applied on startup, a running connector has to be restarted with this flag. The
+    /// completed reindex is recorded, restarts with the same height don't reindex again.
+    #[clap(long)]
+    reindex_from: Option<u64>,
 }
 
 pub async fn main<T: BlockchainClient>() -> Result<()> {
     femme::start();
     let opts = Opts::parse();
 
-    log::info!("connecting to {}", &opts.node_addr);
-    let client = T::new(&opts.network, &opts.node_addr).await?;
-    let indexer = Arc::new(Indexer::new(&opts.path, client)?);
+    if opts.export_snapshot.is_some() || opts.import_snapshot.is_some() {
+        let Some(path) = opts.path else {
+            anyhow::bail!("--path is required to export or import a snapshot");
+        };
+        let config = T::create_config(&opts.network)?;
+        let storage = opts.backend.open(&path)?;
+        if let Some(file) = opts.export_snapshot {
+            let entries = snapshot::export(&*storage, &config, &file)?;
+            log::info!("exported {} entries to {}", entries, file.display());
+        }
+        if let Some(file) = opts.import_snapshot {
+            let entries = snapshot::import(&*storage, &config, &file)?;
+            log::info!("imported {} entries from {}", entries, file.display());
+        }
+        return Ok(());
+    }
+    let Some(addr) = opts.addr else {
+        anyhow::bail!("--addr is required");
+    };
+
+    let metrics = Metrics::default();
+    let state = if opts.offline {
+        log::info!("starting in offline mode");
+        State::offline(T::create_config(&opts.network)?)
+    } else {
+        let (Some(node_addr), Some(path)) = (opts.node_addr, opts.path) else {
+            anyhow::bail!("--node-addr and --path are required unless --offline is set");
+        };
+        log::info!("connecting to {}", &node_addr);
+        let client = MeteredClient::new(T::new(&opts.network, &node_addr).await?, metrics.clone());
+        let storage = opts.backend.open(&path)?;
+        let start_height = match (opts.start_height, opts.start_depth) {
+            (Some(height), _) => StartHeight::Height(height),
+            (_, Some(depth)) => StartHeight::Depth(depth),
+            _ => StartHeight::Genesis,
+        };
+        let indexer = Indexer::new(storage, client)
+            .with_sync_concurrency(opts.sync_concurrency)
+            .with_finalized_only(opts.finalized_only)
+            .with_start_height(start_height)
+            .with_retention(opts.retention);
+        indexer.migrate()?;
+        if let Some(height) = opts.reindex_from {
+            let events = indexer.reindex_from(height)?;
+            log::info!(
+                "reindexing from {}, removed {} blocks",
+                height,
+                events.len()
+            );
+        }
+        let reconciling = !opts.reconcile_accounts.is_empty() || opts.reconcile_sample > 0;
+        if reconciling && !indexer.config().historical_balance_lookup {
+            anyhow::bail!("reconciliation requires a node with historical balance lookups");
+        }
+        State::online(indexer)
+    };
 
     let cors = CorsMiddleware::new()
         .allow_methods("POST".parse::<HeaderValue>().unwrap())
@@ -50,31 +164,200 @@ pub async fn main<T: BlockchainClient>() -> Result<()> {
     let mut app = tide::new();
     app.with(tide::log::LogMiddleware::new());
     app.with(cors);
-    app.at("/").nest(server(indexer.clone()));
-
-    tokio::task::spawn(async move {
-        loop {
-            tokio::time::sleep(Duration::from_secs(10)).await;
-            if let Err(err) = indexer.sync().await {
-                log::error!("{}", err);
-            }
+    app.with(MetricsMiddleware::new(metrics.clone()));
+    let exporter = metrics.clone();
+    app.at("/metrics").get(move |_| {
+        let metrics = exporter.clone();
+        async move {
+            Ok(Response::builder(200)
+                .content_type("text/plain; version=0.0.4")
+                .body(metrics.render())
+                .build())
         }
     });
+    app.at("/").nest(server(state.clone()));
+
+    if let Some(indexer) = state.indexer {
+        let poll_interval = Duration::from_secs(opts.poll_interval);
+        let tracked = opts
+            .reconcile_accounts
+            .into_iter()
+            .map(AccountIdentifier::new)
+            .collect::<Vec<_>>();
+        tokio::task::spawn(async move {
+            let mut heads = Heads::Subscribe;
+            loop {
+                wait_for_head(&**indexer, &mut heads, poll_interval).await;
+                let start = Instant::now();
+                let result = indexer.sync().await;
+                metrics.observe_sync(start.elapsed(), result.is_ok());
+                match indexer.height() {
+                    Ok(height) => metrics.set_indexer_height(height),
+                    Err(err) => log::error!("{}", err),
+                }
+                match result {
+                    Ok(events) => {
+                        for event in &events {
+                            log::debug!(
+                                "event {}: {} {} {}",
+                                event.sequence,
+                                event.r#type.to_string(),
+                                event.block_identifier.index,
+                                event.block_identifier.hash
+                            );
+                        }
+                        if !tracked.is_empty() || opts.reconcile_sample > 0 {
+                            reconcile(&indexer, &events, &tracked, opts.reconcile_sample, &metrics)
+                                .await;
+                        }
+                    }
+                    Err(err) => log::error!("{}", err),
+                }
+            }
+        });
+    }
 
-    log::info!("listening on {}", &opts.addr);
-    app.listen(opts.addr).await?;
+    log::info!("listening on {}", &addr);
+    app.listen(addr).await?;
 
     Ok(())
 }
 
-type State<T> = Arc<Indexer<T>>;
+/// Source of the new heads that trigger a sync.
+enum Heads {
+    /// The node wasn't asked for a subscription yet.
+    Subscribe,
+    Stream(BlockStream),
+    /// The node doesn't support subscriptions or the subscription failed.
+    Poll,
+}
+
+/// Waits until the node announces a new head, or until `poll_interval` elapsed when
+/// no subscription is available. The subscription is requested once, if it can't be
+/// established or ends the indexer falls back to polling.
+async fn wait_for_head<T: BlockchainClient>(
+    client: &T,
+    heads: &mut Heads,
+    poll_interval: Duration,
+) {
+    if let Heads::Subscribe = heads {
+        *heads = match client.listen().await {
+            Ok(Some(stream)) => Heads::Stream(stream),
+            Ok(None) => Heads::Poll,
+            Err(err) => {
+                log::warn!(
+                    "failed to subscribe to new heads, falling back to polling: {}",
+                    err
+                );
+                Heads::Poll
+            }
+        };
+    }
+    let Heads::Stream(stream) = heads else {
+        tokio::time::sleep(poll_interval).await;
+        return;
+    };
+    match tokio::time::timeout(poll_interval, stream.next()).await {
+        Ok(Some(head)) => log::debug!("new head {} {}", head.index, head.hash),
+        Ok(None) => {
+            log::warn!("head subscription closed, falling back to polling");
+            *heads = Heads::Poll;
+        }
+        // no head arrived in time, sync anyway in case one was missed
+        Err(_) => {}
+    }
+}
+
+/// Reconciles the blocks added by a sync and reports every balance change that doesn't
+/// add up to the operations of its block.
+async fn reconcile<T: BlockchainClient>(
+    indexer: &Indexer<T>,
+    events: &[BlockEvent],
+    tracked: &[AccountIdentifier],
+    sample: usize,
+    metrics: &Metrics,
+) {
+    for event in events {
+        if event.r#type != BlockEventType::Added {
+            continue;
+        }
+        let block_index = event.block_identifier.index;
+        let mismatches = match indexer.reconciliation_accounts(block_index, tracked, sample) {
+            Ok(accounts) => indexer
+                .reconcile(block_index, &accounts)
+                .await
+                .map(|mismatches| (accounts.len(), mismatches)),
+            Err(err) => Err(err),
+        };
+        match mismatches {
+            Ok((reconciled, mismatches)) => {
+                metrics.observe_reconciliation(reconciled, mismatches.len());
+                for mismatch in mismatches {
+                    log::error!(
+                        "balance mismatch: {}",
+                        serde_json::to_string(&mismatch).unwrap()
+                    );
+                }
+            }
+            Err(err) => log::error!("failed to reconcile block {}: {}", block_index, err),
+        }
+    }
+}
+
+struct State<T: BlockchainClient> {
+    config: Arc<BlockchainConfig>,
+    indexer: Option<Arc<Indexer<T>>>,
+}
 
-fn server<T: BlockchainClient>(client: State<T>) -> tide::Server<State<T>> {
-    let config = client.config();
+impl<T: BlockchainClient> Clone for State<T> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            indexer: self.indexer.clone(),
+        }
+    }
+}
+
+impl<T: BlockchainClient> State<T> {
+    fn online(indexer: Indexer<T>) -> Self {
+        Self {
+            config: Arc::new(indexer.config().clone()),
+            indexer: Some(Arc::new(indexer)),
+        }
+    }
+
+    fn offline(config: BlockchainConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            indexer: None,
+        }
+    }
+
+    fn config(&self) -> &BlockchainConfig {
+        &self.config
+    }
+
+    /// Returns the indexer, or an error when running in offline mode.
+    fn indexer(&self) -> Result<&Indexer<T>, Error> {
+        self.indexer.as_deref().ok_or(Error::Offline)
+    }
+}
+
+fn server<T: BlockchainClient>(state: State<T>) -> tide::Server<State<T>> {
+    let config = state.config();
     let utxo = config.utxo;
     let testnet = config.testnet;
-    let mut app = tide::with_state(client);
+    let mut app = tide::with_state(state);
+    // turns errors raised with `?`, including malformed request bodies, into rosetta errors
+    app.with(After(|mut res: Response| async move {
+        Ok(match res.take_error() {
+            Some(err) => Error::from(err).to_response(),
+            None => res,
+        })
+    }));
     app.at("/account/balance").post(account_balance);
+    app.at("/account/balance/history")
+        .post(account_balance_history);
     if utxo {
         app.at("/account/coins").post(account_coins);
     }
@@ -84,22 +367,22 @@ fn server<T: BlockchainClient>(client: State<T>) -> tide::Server<State<T>> {
     app.at("/block").post(block);
     app.at("/block/transaction").post(block_transaction);
     app.at("/call").post(call);
+    app.at("/construction/combine").post(construction_combine);
+    app.at("/construction/derive").post(construction_derive);
+    app.at("/construction/hash").post(construction_hash);
     app.at("/construction/metadata").post(construction_metadata);
+    app.at("/construction/parse").post(construction_parse);
+    app.at("/construction/payloads").post(construction_payloads);
+    app.at("/construction/preprocess")
+        .post(construction_preprocess);
     app.at("/construction/submit").post(construction_submit);
+    app.at("/events/blocks").post(events_blocks);
+    app.at("/mempool").post(mempool);
+    app.at("/mempool/transaction").post(mempool_transaction);
     app.at("/network/list").post(network_list);
     app.at("/network/options").post(network_options);
     app.at("/network/status").post(network_status);
     app.at("/search/transactions").post(search_transactions);
-    // unsupported
-    app.at("/mempool").post(unsupported);
-    app.at("/mempool/transaction").post(unsupported);
-    app.at("/construction/combine").post(unsupported);
-    app.at("/construction/derive").post(unsupported);
-    app.at("/construction/hash").post(unsupported);
-    app.at("/construction/parse").post(unsupported);
-    app.at("/construction/payloads").post(unsupported);
-    app.at("/construction/preprocess").post(unsupported);
-    app.at("/events/blocks").post(unsupported);
     app
 }
 
@@ -116,6 +399,19 @@ fn is_network_supported(network_identifier: &NetworkIdentifier, config: &Blockch
         && network_identifier.sub_network_identifier.is_none()
 }
 
+/// Rejects negative paging and block parameters, they are used as unsigned indexes.
+fn check_non_negative(params: &[(&str, Option<i64>)]) -> Result<(), Error> {
+    for (name, value) in params {
+        if value.map(|value| value < 0).unwrap_or_default() {
+            return Err(Error::InvalidRequest(anyhow::anyhow!(
+                "{} must not be negative",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
 async fn network_list<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
     let _request: MetadataRequest = req.body_json().await?;
     let config = req.state().config();
@@ -135,9 +431,31 @@ async fn network_options<T: BlockchainClient>(mut req: Request<State<T>>) -> tid
     if !is_network_supported(&request.network_identifier, config) {
         return Error::UnsupportedNetwork.to_result();
     }
-    let node_version = match req.state().node_version().await {
-        Ok(node_version) => node_version,
-        Err(err) => return Error::RpcError(err).to_result(),
+    let mut allow = Allow {
+        operation_statuses: vec![],
+        operation_types: vec![],
+        errors: Error::catalog(),
+        historical_balance_lookup: config.historical_balance_lookup,
+        timestamp_start_index: None,
+        call_methods: None,
+        balance_exemptions: None,
+        mempool_coins: false,
+        block_hash_case: None,
+        transaction_hash_case: None,
+    };
+    let node_version = if let Ok(indexer) = req.state().indexer() {
+        allow.operation_statuses = indexer.operation_statuses();
+        allow.operation_types = indexer.operation_types();
+        allow.call_methods = Some(indexer.call_methods());
+        allow.balance_exemptions = Some(indexer.balance_exemptions());
+        match indexer.node_version().await {
+            Ok(node_version) => node_version,
+            Err(err) => return Error::rpc(err).to_result(),
+        }
+    } else {
+        // without a node there is no node version, the connector version is reported as
+        // the middleware version
+        String::new()
     };
     let response = NetworkOptionsResponse {
         version: Version {
@@ -146,7 +464,7 @@ async fn network_options<T: BlockchainClient>(mut req: Request<State<T>>) -> tid
             middleware_version: Some(env!("VERGEN_GIT_DESCRIBE").into()),
             metadata: None,
         },
-        allow: None,
+        allow: Some(allow),
     };
     ok(&response)
 }
@@ -157,17 +475,50 @@ async fn network_status<T: BlockchainClient>(mut req: Request<State<T>>) -> tide
     if !is_network_supported(&request.network_identifier, config) {
         return Error::UnsupportedNetwork.to_result();
     }
-    let current_block_identifier = match req.state().current_block().await {
+    let indexer = match req.state().indexer() {
+        Ok(indexer) => indexer,
+        Err(err) => return err.to_result(),
+    };
+    let current_block_identifier = match indexer.current_block().await {
         Ok(current_block_identifier) => current_block_identifier,
-        Err(err) => return Error::RpcError(err).to_result(),
+        Err(err) => return Error::rpc(err).to_result(),
+    };
+    let current_block_timestamp = match indexer.block_timestamp(&current_block_identifier).await {
+        Ok(current_block_timestamp) => current_block_timestamp,
+        Err(err) => return Error::rpc(err).to_result(),
+    };
+    let peers = match indexer.peers().await {
+        Ok(peers) => peers,
+        Err(err) => return Error::rpc(err).to_result(),
+    };
+    let syncing = match indexer.syncing().await {
+        Ok(syncing) => syncing,
+        Err(err) => return Error::rpc(err).to_result(),
+    };
+    // with --finalized-only the indexer is synced once it reaches the finalized block
+    let sync_target = if indexer.finalized_only() {
+        match indexer.finalized_block().await {
+            Ok(finalized_block) => finalized_block,
+            Err(err) => return Error::rpc(err).to_result(),
+        }
+    } else {
+        current_block_identifier.clone()
+    };
+    let sync_status = match indexer.sync_status(&sync_target, syncing) {
+        Ok(sync_status) => sync_status,
+        Err(err) => return Error::rpc(err).to_result(),
+    };
+    let oldest_block_identifier = match indexer.oldest_block() {
+        Ok(oldest_block_identifier) => oldest_block_identifier,
+        Err(err) => return Error::rpc(err).to_result(),
     };
     let response = NetworkStatusResponse {
         current_block_identifier,
-        current_block_timestamp: 0,
-        genesis_block_identifier: Some(req.state().genesis_block().clone()),
-        peers: None,
-        oldest_block_identifier: None,
-        sync_status: None,
+        current_block_timestamp,
+        genesis_block_identifier: Some(indexer.genesis_block().clone()),
+        peers: Some(peers),
+        oldest_block_identifier,
+        sync_status: Some(sync_status),
     };
     ok(&response)
 }
@@ -178,18 +529,42 @@ async fn account_balance<T: BlockchainClient>(mut req: Request<State<T>>) -> tid
     if !is_network_supported(&request.network_identifier, config) {
         return Error::UnsupportedNetwork.to_result();
     }
-    let block_identifier = match req.state().current_block().await {
+    let indexer = match req.state().indexer() {
+        Ok(indexer) => indexer,
+        Err(err) => return err.to_result(),
+    };
+    // nodes that can't look up historical balances are answered from the balance history
+    let historical = request.block_identifier.is_some() && !config.historical_balance_lookup;
+    let block_identifier = if let Some(block_identifier) = request.block_identifier.as_ref() {
+        indexer.block_identifier(block_identifier).await
+    } else {
+        indexer.current_block().await
+    };
+    let block_identifier = match block_identifier {
         Ok(block_identifier) => block_identifier,
-        Err(err) => return Error::RpcError(err).to_result(),
+        Err(err) => return Error::rpc(err).to_result(),
     };
-    let address = Address::new(config.address_format, request.account_identifier.address);
-    let value = match req.state().balance(&address, &block_identifier).await {
-        Ok(value) => value,
-        Err(err) => return Error::RpcError(err).to_result(),
+    let value = if historical {
+        let balance = indexer.balance_at(
+            &request.account_identifier,
+            &config.currency(),
+            block_identifier.index,
+        );
+        match balance {
+            Ok(Some(value)) => value.to_string(),
+            Ok(None) => return Error::HistoricalBalanceLookupUnsupported.to_result(),
+            Err(err) => return Error::rpc(err).to_result(),
+        }
+    } else {
+        let address = Address::new(config.address_format, request.account_identifier.address);
+        match indexer.balance(&address, &block_identifier).await {
+            Ok(value) => value.to_string(),
+            Err(err) => return Error::rpc(err).to_result(),
+        }
     };
     let response = AccountBalanceResponse {
         balances: vec![Amount {
-            value: value.to_string(),
+            value,
             currency: config.currency(),
             metadata: None,
         }],
@@ -199,20 +574,50 @@ async fn account_balance<T: BlockchainClient>(mut req: Request<State<T>>) -> tid
     ok(&response)
 }
 
+async fn account_balance_history<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
+    let request: AccountBalanceHistoryRequest = req.body_json().await?;
+    let config = req.state().config();
+    if !is_network_supported(&request.network_identifier, config) {
+        return Error::UnsupportedNetwork.to_result();
+    }
+    let params = [
+        ("min_block", request.min_block),
+        ("max_block", request.max_block),
+        ("offset", request.offset),
+        ("limit", request.limit),
+    ];
+    if let Err(err) = check_non_negative(&params) {
+        return err.to_result();
+    }
+    let indexer = match req.state().indexer() {
+        Ok(indexer) => indexer,
+        Err(err) => return err.to_result(),
+    };
+    let response = match indexer.balance_history(&request) {
+        Ok(response) => response,
+        Err(err) => return Error::rpc(err).to_result(),
+    };
+    ok(&response)
+}
+
 async fn account_coins<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
     let request: AccountCoinsRequest = req.body_json().await?;
     let config = req.state().config();
     if !is_network_supported(&request.network_identifier, config) {
         return Error::UnsupportedNetwork.to_result();
     }
-    let block_identifier = match req.state().current_block().await {
+    let indexer = match req.state().indexer() {
+        Ok(indexer) => indexer,
+        Err(err) => return err.to_result(),
+    };
+    let block_identifier = match indexer.current_block().await {
         Ok(block_identifier) => block_identifier,
-        Err(err) => return Error::RpcError(err).to_result(),
+        Err(err) => return Error::rpc(err).to_result(),
     };
     let address = Address::new(config.address_format, request.account_identifier.address);
-    let coins = match req.state().coins(&address, &block_identifier).await {
+    let coins = match indexer.coins(&address, &block_identifier).await {
         Ok(coins) => coins,
-        Err(err) => return Error::RpcError(err).to_result(),
+        Err(err) => return Error::rpc(err).to_result(),
     };
     let response = AccountCoinsResponse {
         coins,
@@ -228,10 +633,14 @@ async fn account_faucet<T: BlockchainClient>(mut req: Request<State<T>>) -> tide
     if !is_network_supported(&request.network_identifier, config) {
         return Error::UnsupportedNetwork.to_result();
     }
+    let indexer = match req.state().indexer() {
+        Ok(indexer) => indexer,
+        Err(err) => return err.to_result(),
+    };
     let address = Address::new(config.address_format, request.account_identifier.address);
-    let hash = match req.state().faucet(&address, request.faucet_parameter).await {
+    let hash = match indexer.faucet(&address, request.faucet_parameter).await {
         Ok(hash) => hash,
-        Err(err) => return Error::RpcError(err).to_result(),
+        Err(err) => return Error::rpc(err).to_result(),
     };
     let response = TransactionIdentifierResponse {
         transaction_identifier: TransactionIdentifier {
@@ -242,12 +651,58 @@ async fn account_faucet<T: BlockchainClient>(mut req: Request<State<T>>) -> tide
     ok(&response)
 }
 
+#[derive(Deserialize)]
+struct DeriveMetadata {
+    ss58_prefix: Option<u16>,
+}
+
+async fn construction_derive<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
+    let request: ConstructionDeriveRequest = req.body_json().await?;
+    let config = req.state().config();
+    if !is_network_supported(&request.network_identifier, config) {
+        return Error::UnsupportedNetwork.to_result();
+    }
+    if request.public_key.curve_type != config.algorithm.to_curve_type() {
+        return Error::UnsupportedCurveType.to_result();
+    }
+    let mut address_format = config.address_format;
+    if let Some(metadata) = request.metadata {
+        let metadata: DeriveMetadata = serde_json::from_value(metadata)?;
+        if let Some(prefix) = metadata.ss58_prefix {
+            let AddressFormat::Ss58(_) = address_format else {
+                return Error::UnsupportedOption.to_result();
+            };
+            address_format = AddressFormat::Ss58(Ss58AddressFormat::custom(prefix));
+        }
+    }
+    let public_key_bytes = hex::decode(&request.public_key.hex_bytes)?;
+    let public_key = match PublicKey::from_bytes(config.algorithm, &public_key_bytes) {
+        Ok(public_key) => public_key,
+        Err(err) => return Error::InvalidPublicKey(err).to_result(),
+    };
+    let address = public_key.to_address(address_format);
+    let response = ConstructionDeriveResponse {
+        address: None,
+        account_identifier: Some(AccountIdentifier {
+            address: address.address().into(),
+            sub_account: None,
+            metadata: None,
+        }),
+        metadata: None,
+    };
+    ok(&response)
+}
+
 async fn construction_metadata<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
     let request: ConstructionMetadataRequest = req.body_json().await?;
     let config = req.state().config();
     if !is_network_supported(&request.network_identifier, config) {
         return Error::UnsupportedNetwork.to_result();
     }
+    let indexer = match req.state().indexer() {
+        Ok(indexer) => indexer,
+        Err(err) => return err.to_result(),
+    };
     let options: T::MetadataParams = if let Some(options) = request.options {
         serde_json::from_value(options)?
     } else {
@@ -261,10 +716,13 @@ async fn construction_metadata<T: BlockchainClient>(mut req: Request<State<T>>)
         return Error::UnsupportedCurveType.to_result();
     }
     let public_key_bytes = hex::decode(&public_key.hex_bytes)?;
-    let public_key = PublicKey::from_bytes(config.algorithm, &public_key_bytes)?;
-    let metadata = match req.state().metadata(&public_key, &options).await {
+    let public_key = match PublicKey::from_bytes(config.algorithm, &public_key_bytes) {
+        Ok(public_key) => public_key,
+        Err(err) => return Error::InvalidPublicKey(err).to_result(),
+    };
+    let metadata = match indexer.metadata(&public_key, &options).await {
         Ok(metadata) => metadata,
-        Err(err) => return Error::RpcError(err).to_result(),
+        Err(err) => return Error::rpc(err).to_result(),
     };
     let response = ConstructionMetadataResponse {
         metadata: serde_json::to_value(&metadata)?,
@@ -273,16 +731,230 @@ async fn construction_metadata<T: BlockchainClient>(mut req: Request<State<T>>)
     ok(&response)
 }
 
+/// Transaction intended by the operations of a construction request.
+struct Intent<P> {
+    sender: AccountIdentifier,
+    metadata_params: P,
+}
+
+/// Derives the transaction intended by `operations`, a transfer of the native currency
+/// described by a debit of the sender and a credit of the receiver.
+fn intent<B: TransactionBuilder>(
+    config: &BlockchainConfig,
+    operations: &[Operation],
+) -> Result<Intent<B::MetadataParams>, Error> {
+    let invalid = |msg: &'static str| Error::InvalidRequest(anyhow::anyhow!(msg));
+    let [debit, credit] = match operations {
+        [a, b]
+            if a.amount
+                .as_ref()
+                .map(|amount| amount.value.starts_with('-'))
+                == Some(true) =>
+        {
+            [a, b]
+        }
+        [a, b] => [b, a],
+        _ => return Err(invalid("expected the two operations of a transfer")),
+    };
+    let (Some(sender), Some(receiver)) = (&debit.account, &credit.account) else {
+        return Err(invalid("transfer operations require an account"));
+    };
+    let (Some(debit), Some(credit)) = (&debit.amount, &credit.amount) else {
+        return Err(invalid("transfer operations require an amount"));
+    };
+    if debit.currency != config.currency() || credit.currency != config.currency() {
+        return Err(invalid(
+            "only transfers of the native currency are supported",
+        ));
+    }
+    let amount = credit
+        .value
+        .parse::<u128>()
+        .map_err(|err| Error::InvalidRequest(err.into()))?;
+    if debit.value != format!("-{amount}") {
+        return Err(invalid("debit and credit of a transfer must match"));
+    }
+    let receiver = Address::new(config.address_format, receiver.address.clone());
+    let metadata_params = B::default()
+        .transfer(&receiver, amount)
+        .map_err(Error::InvalidRequest)?;
+    Ok(Intent {
+        sender: sender.clone(),
+        metadata_params,
+    })
+}
+
+async fn construction_preprocess<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
+    let request: ConstructionPreprocessRequest = req.body_json().await?;
+    let config = req.state().config();
+    if !is_network_supported(&request.network_identifier, config) {
+        return Error::UnsupportedNetwork.to_result();
+    }
+    let intent = match intent::<T::TransactionBuilder>(config, &request.operations) {
+        Ok(intent) => intent,
+        Err(err) => return err.to_result(),
+    };
+    let response = ConstructionPreprocessResponse {
+        options: Some(serde_json::to_value(&intent.metadata_params)?),
+        required_public_keys: Some(vec![intent.sender]),
+    };
+    ok(&response)
+}
+
+async fn construction_payloads<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
+    let request: ConstructionPayloadsRequest = req.body_json().await?;
+    let config = req.state().config();
+    if !is_network_supported(&request.network_identifier, config) {
+        return Error::UnsupportedNetwork.to_result();
+    }
+    let intent = match intent::<T::TransactionBuilder>(config, &request.operations) {
+        Ok(intent) => intent,
+        Err(err) => return err.to_result(),
+    };
+    let metadata: T::Metadata = if let Some(metadata) = request.metadata {
+        serde_json::from_value(metadata)?
+    } else {
+        return Error::UnsupportedOption.to_result();
+    };
+    let public_keys = request.public_keys.unwrap_or_default();
+    if public_keys.len() != 1 {
+        return Error::MissingPublicKey.to_result();
+    }
+    let public_key = &public_keys[0];
+    if public_key.curve_type != config.algorithm.to_curve_type() {
+        return Error::UnsupportedCurveType.to_result();
+    }
+    let public_key_bytes = hex::decode(&public_key.hex_bytes)?;
+    let public_key = match PublicKey::from_bytes(config.algorithm, &public_key_bytes) {
+        Ok(public_key) => public_key,
+        Err(err) => return Error::InvalidPublicKey(err).to_result(),
+    };
+    let unsigned = match T::TransactionBuilder::default().create_unsigned(
+        config,
+        &intent.metadata_params,
+        &metadata,
+        &public_key,
+    ) {
+        Ok(unsigned) => unsigned,
+        Err(err) => return Error::InvalidTransaction(err).to_result(),
+    };
+    let address = public_key.to_address(config.address_format);
+    let response = ConstructionPayloadsResponse {
+        unsigned_transaction: hex::encode(unsigned.transaction),
+        payloads: vec![SigningPayload {
+            address: None,
+            account_identifier: Some(AccountIdentifier {
+                address: address.address().into(),
+                sub_account: None,
+                metadata: None,
+            }),
+            hex_bytes: hex::encode(unsigned.signing_payload),
+            signature_type: Some(config.algorithm.to_signature_type()),
+        }],
+    };
+    ok(&response)
+}
+
+async fn construction_combine<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
+    let request: ConstructionCombineRequest = req.body_json().await?;
+    let config = req.state().config();
+    if !is_network_supported(&request.network_identifier, config) {
+        return Error::UnsupportedNetwork.to_result();
+    }
+    if request.signatures.len() != 1 {
+        return Error::MoreThanOneSignature.to_result();
+    }
+    let signature = &request.signatures[0];
+    if signature.signature_type != config.algorithm.to_signature_type() {
+        return Error::InvalidSignatureType.to_result();
+    }
+    let unsigned_transaction = hex::decode(&request.unsigned_transaction)?;
+    let signature_bytes = hex::decode(&signature.hex_bytes)?;
+    let signature = match Signature::from_bytes(config.algorithm, &signature_bytes) {
+        Ok(signature) => signature,
+        Err(err) => return Error::InvalidSignature(err).to_result(),
+    };
+    let transaction =
+        match T::TransactionBuilder::default().combine(config, &unsigned_transaction, &signature) {
+            Ok(transaction) => transaction,
+            Err(err) => return Error::InvalidTransaction(err).to_result(),
+        };
+    let response = ConstructionCombineResponse {
+        signed_transaction: hex::encode(transaction),
+    };
+    ok(&response)
+}
+
+async fn construction_parse<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
+    let request: ConstructionParseRequest = req.body_json().await?;
+    let config = req.state().config();
+    if !is_network_supported(&request.network_identifier, config) {
+        return Error::UnsupportedNetwork.to_result();
+    }
+    let transaction = hex::decode(&request.transaction)?;
+    let parsed = match T::TransactionBuilder::default().parse(config, &transaction, request.signed)
+    {
+        Ok(parsed) => parsed,
+        Err(err) => return Error::InvalidTransaction(err).to_result(),
+    };
+    let mut metadata = match parsed.metadata {
+        Some(serde_json::Value::Object(metadata)) => metadata,
+        _ => Default::default(),
+    };
+    metadata.insert("signed".into(), parsed.signed.into());
+    let signers = request.signed.then_some(parsed.signers);
+    let response = ConstructionParseResponse {
+        operations: parsed.operations,
+        account_identifier_signers: signers.as_ref().map(|signers| {
+            signers
+                .iter()
+                .map(|address| AccountIdentifier {
+                    address: address.clone(),
+                    sub_account: None,
+                    metadata: None,
+                })
+                .collect()
+        }),
+        signers,
+        metadata: Some(metadata.into()),
+    };
+    ok(&response)
+}
+
+async fn construction_hash<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
+    let request: ConstructionHashRequest = req.body_json().await?;
+    let config = req.state().config();
+    if !is_network_supported(&request.network_identifier, config) {
+        return Error::UnsupportedNetwork.to_result();
+    }
+    let transaction = hex::decode(&request.signed_transaction)?;
+    let hash = match T::TransactionBuilder::default().hash(config, &transaction) {
+        Ok(hash) => hash,
+        Err(err) => return Error::InvalidTransaction(err).to_result(),
+    };
+    let response = TransactionIdentifierResponse {
+        transaction_identifier: TransactionIdentifier {
+            hash: hex::encode(hash),
+        },
+        metadata: None,
+    };
+    ok(&response)
+}
+
 async fn construction_submit<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
     let request: ConstructionSubmitRequest = req.body_json().await?;
     let config = req.state().config();
     if !is_network_supported(&request.network_identifier, config) {
         return Error::UnsupportedNetwork.to_result();
     }
+    let indexer = match req.state().indexer() {
+        Ok(indexer) => indexer,
+        Err(err) => return err.to_result(),
+    };
     let transaction = hex::decode(&request.signed_transaction)?;
-    let hash = match req.state().submit(&transaction).await {
+    let hash = match indexer.submit(&transaction).await {
         Ok(hash) => hash,
-        Err(err) => return Error::RpcError(err).to_result(),
+        Err(err) => return Error::rpc(err).to_result(),
     };
     let response = TransactionIdentifierResponse {
         transaction_identifier: TransactionIdentifier {
@@ -299,9 +971,13 @@ async fn block<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result
     if !is_network_supported(&request.network_identifier, config) {
         return Error::UnsupportedNetwork.to_result();
     }
-    let block = match req.state().block(&request.block_identifier).await {
+    let indexer = match req.state().indexer() {
+        Ok(indexer) => indexer,
+        Err(err) => return err.to_result(),
+    };
+    let block = match indexer.block(&request.block_identifier).await {
         Ok(block) => block,
-        Err(err) => return Error::RpcError(err).to_result(),
+        Err(err) => return Error::rpc(err).to_result(),
     };
     let response = BlockResponse {
         block: Some(block),
@@ -316,111 +992,137 @@ async fn block_transaction<T: BlockchainClient>(mut req: Request<State<T>>) -> t
     if !is_network_supported(&request.network_identifier, config) {
         return Error::UnsupportedNetwork.to_result();
     }
-    let transaction = match req
-        .state()
+    let indexer = match req.state().indexer() {
+        Ok(indexer) => indexer,
+        Err(err) => return err.to_result(),
+    };
+    let transaction = match indexer
         .block_transaction(&request.block_identifier, &request.transaction_identifier)
         .await
     {
         Ok(transaction) => transaction,
-        Err(err) => return Error::RpcError(err).to_result(),
+        Err(err) => return Error::rpc(err).to_result(),
     };
     let response = BlockTransactionResponse { transaction };
     ok(&response)
 }
 
-async fn search_transactions<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
-    let request: SearchTransactionsRequest = req.body_json().await?;
+async fn mempool<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
+    let request: NetworkRequest = req.body_json().await?;
     let config = req.state().config();
     if !is_network_supported(&request.network_identifier, config) {
         return Error::UnsupportedNetwork.to_result();
     }
-    let response = match req.state().search(&request).await {
-        Ok(response) => response,
-        Err(err) => return Error::RpcError(err).to_result(),
+    let indexer = match req.state().indexer() {
+        Ok(indexer) => indexer,
+        Err(err) => return err.to_result(),
+    };
+    let transaction_identifiers = match indexer.mempool().await {
+        Ok(transaction_identifiers) => transaction_identifiers,
+        Err(err) => return Error::rpc(err).to_result(),
+    };
+    let response = MempoolResponse {
+        transaction_identifiers,
     };
     ok(&response)
 }
 
-async fn call<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
-    let request: CallRequest = req.body_json().await?;
+async fn mempool_transaction<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
+    let request: MempoolTransactionRequest = req.body_json().await?;
     let config = req.state().config();
     if !is_network_supported(&request.network_identifier, config) {
         return Error::UnsupportedNetwork.to_result();
     }
-    let call_result = match req.state().call(&request).await {
-        Ok(call_result) => call_result,
-        Err(err) => return Error::RpcError(err).to_result(),
+    let indexer = match req.state().indexer() {
+        Ok(indexer) => indexer,
+        Err(err) => return err.to_result(),
     };
-    let response = CallResponse {
-        result: call_result,
-        idempotent: false,
+    let transaction = match indexer
+        .mempool_transaction(&request.transaction_identifier)
+        .await
+    {
+        Ok(transaction) => transaction,
+        Err(err) => return Error::rpc(err).to_result(),
+    };
+    let response = MempoolTransactionResponse {
+        transaction,
+        metadata: None,
     };
     ok(&response)
 }
 
-async fn unsupported<T>(_: Request<T>) -> tide::Result {
-    Error::Unsupported.to_result()
-}
-
-#[derive(Debug)]
-pub enum Error {
-    Unimplemented,
-    Unsupported,
-    UnsupportedNetwork,
-    UnsupportedOption,
-    MissingPublicKey,
-    UnsupportedCurveType,
-    MoreThanOneSignature,
-    InvalidSignatureType,
-    RpcError(anyhow::Error),
-}
-
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let msg = match self {
-            Self::Unimplemented => "unimplemented",
-            Self::Unsupported => "unsupported",
-            Self::UnsupportedNetwork => "unsupported network",
-            Self::UnsupportedOption => "unsupported option",
-            Self::MissingPublicKey => "missing public key",
-            Self::UnsupportedCurveType => "unsupported curve type",
-            Self::MoreThanOneSignature => "expected one signature",
-            Self::InvalidSignatureType => "invalid signature type",
-            Self::RpcError(error) => return write!(f, "rpc error: {error}",),
-        };
-        f.write_str(msg)
+async fn events_blocks<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
+    let request: EventsBlocksRequest = req.body_json().await?;
+    let config = req.state().config();
+    if !is_network_supported(&request.network_identifier, config) {
+        return Error::UnsupportedNetwork.to_result();
     }
+    let indexer = match req.state().indexer() {
+        Ok(indexer) => indexer,
+        Err(err) => return err.to_result(),
+    };
+    let response = match indexer.events(&request) {
+        Ok(response) => response,
+        Err(err) => return Error::rpc(err).to_result(),
+    };
+    ok(&response)
 }
 
-impl Error {
-    pub fn error(&self) -> Option<&anyhow::Error> {
-        let error = match self {
-            Self::RpcError(error) => error,
-            _ => return None,
-        };
-        Some(error)
+async fn search_transactions<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
+    let request: SearchTransactionsRequest = req.body_json().await?;
+    let config = req.state().config();
+    if !is_network_supported(&request.network_identifier, config) {
+        return Error::UnsupportedNetwork.to_result();
     }
-
-    pub fn description(&self) -> Option<String> {
-        self.error().map(|error| error.to_string())
+    let params = [
+        ("max_block", request.max_block),
+        ("offset", request.offset),
+        ("limit", request.limit),
+    ];
+    if let Err(err) = check_non_negative(&params) {
+        return err.to_result();
     }
-
-    pub fn to_response(&self) -> Response {
-        let error = rosetta_core::types::Error {
-            code: 500,
-            message: self.to_string(),
-            description: self.description(),
-            retriable: false,
-            details: None,
-        };
-        Response::builder(500)
-            .body(Body::from_json(&error).unwrap())
-            .build()
+    if let Some(cursor) = request.cursor.as_deref() {
+        if let Err(err) = decode_cursor(cursor) {
+            return Error::InvalidRequest(err).to_result();
+        }
+        if request.offset.unwrap_or_default() > 0 {
+            return Error::InvalidRequest(anyhow::anyhow!(
+                "offset can't be combined with a cursor"
+            ))
+            .to_result();
+        }
     }
+    let indexer = match req.state().indexer() {
+        Ok(indexer) => indexer,
+        Err(err) => return err.to_result(),
+    };
+    let response = match indexer.search(&request).await {
+        Ok(response) => response,
+        Err(err) => return Error::rpc(err).to_result(),
+    };
+    ok(&response)
+}
 
-    pub fn to_result(&self) -> tide::Result {
-        Ok(self.to_response())
+async fn call<T: BlockchainClient>(mut req: Request<State<T>>) -> tide::Result {
+    let request: CallRequest = req.body_json().await?;
+    let config = req.state().config();
+    if !is_network_supported(&request.network_identifier, config) {
+        return Error::UnsupportedNetwork.to_result();
     }
+    let indexer = match req.state().indexer() {
+        Ok(indexer) => indexer,
+        Err(err) => return err.to_result(),
+    };
+    let call_result = match indexer.call(&request).await {
+        Ok(call_result) => call_result,
+        Err(err) => return Error::rpc(err).to_result(),
+    };
+    let response = CallResponse {
+        result: call_result,
+        idempotent: false,
+    };
+    ok(&response)
 }
 
 #[cfg(feature = "tests")]
@@ -448,10 +1150,18 @@ pub mod tests {
 
         let client = env.node::<T>().await?;
         let version = client.node_version().await?;
+        let operation_types = client.operation_types();
 
         let client = env.connector()?;
         let options = client.network_options(config.network()).await?;
         assert_eq!(options.version.node_version, version);
+        let allow = options.allow.unwrap();
+        assert_eq!(
+            allow.historical_balance_lookup,
+            config.historical_balance_lookup
+        );
+        assert_eq!(allow.errors, Error::catalog());
+        assert_eq!(allow.operation_types, operation_types);
 
         env.shutdown().await?;
         Ok(())
@@ -468,6 +1178,8 @@ pub mod tests {
         let status = client.network_status(config.network()).await?;
         assert_eq!(status.genesis_block_identifier, Some(genesis));
         assert_eq!(status.current_block_identifier, current);
+        let sync_status = status.sync_status.unwrap();
+        assert_eq!(sync_status.target_index, Some(current.index as _));
 
         env.shutdown().await?;
         Ok(())
@@ -488,6 +1200,46 @@ pub mod tests {
         Ok(())
     }
 
+    pub async fn construction_derive(config: BlockchainConfig) -> Result<()> {
+        let env = Env::new("construction-derive", config.clone()).await?;
+
+        let wallet = env.ephemeral_wallet()?;
+        let client = env.connector()?;
+        let response = client
+            .construction_derive(&ConstructionDeriveRequest {
+                network_identifier: config.network(),
+                public_key: wallet.public_key().clone(),
+                metadata: None,
+            })
+            .await?;
+        assert_eq!(response.account_identifier.as_ref(), Some(wallet.account()));
+
+        env.shutdown().await?;
+        Ok(())
+    }
+
+    pub async fn mempool(config: BlockchainConfig) -> Result<()> {
+        let env = Env::new("mempool", config.clone()).await?;
+
+        let client = env.connector()?;
+        let response = client.mempool(config.network()).await?;
+        for transaction_identifier in response.transaction_identifiers {
+            let response = client
+                .mempool_transaction(&MempoolTransactionRequest {
+                    network_identifier: config.network(),
+                    transaction_identifier: transaction_identifier.clone(),
+                })
+                .await?;
+            assert_eq!(
+                response.transaction.transaction_identifier,
+                transaction_identifier
+            );
+        }
+
+        env.shutdown().await?;
+        Ok(())
+    }
+
     pub async fn construction(config: BlockchainConfig) -> Result<()> {
         let env = Env::new("construction", config.clone()).await?;
 
@@ -0,0 +1,454 @@
+use crate::crypto::address::Address;
+use crate::crypto::PublicKey;
+use crate::types::{
+    BalanceExemption, Block, BlockIdentifier, CallRequest, Coin, OperationStatus,
+    PartialBlockIdentifier, Peer, Transaction, TransactionIdentifier,
+};
+use crate::{BlockStream, BlockchainClient, BlockchainConfig};
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tide::{Middleware, Next, Request, Response};
+
+/// Upper bounds of the latency histogram buckets in seconds.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Clone, Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if secs <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS) {
+            writeln!(out, "{name}_bucket{{{labels}{sep}le=\"{bound}\"}} {bucket}").unwrap();
+        }
+        writeln!(
+            out,
+            "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {}",
+            self.count
+        )
+        .unwrap();
+        writeln!(out, "{name}_sum{{{labels}}} {}", self.sum).unwrap();
+        writeln!(out, "{name}_count{{{labels}}} {}", self.count).unwrap();
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    requests: BTreeMap<(String, u16), u64>,
+    request_latency: BTreeMap<String, Histogram>,
+    rpc_latency: BTreeMap<&'static str, Histogram>,
+    rpc_errors: BTreeMap<&'static str, u64>,
+    indexer_height: u64,
+    chain_tip: u64,
+    sync_duration: Histogram,
+    sync_failures: u64,
+    reconciled_balances: u64,
+    balance_mismatches: u64,
+}
+
+/// Connector metrics rendered in the prometheus text format.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Metrics {
+    pub fn observe_request(&self, route: &str, status: u16, duration: Duration) {
+        let mut registry = self.registry.lock().unwrap();
+        *registry
+            .requests
+            .entry((route.to_string(), status))
+            .or_default() += 1;
+        registry
+            .request_latency
+            .entry(route.to_string())
+            .or_default()
+            .observe(duration);
+    }
+
+    pub fn observe_rpc(&self, method: &'static str, duration: Duration, success: bool) {
+        let mut registry = self.registry.lock().unwrap();
+        registry
+            .rpc_latency
+            .entry(method)
+            .or_default()
+            .observe(duration);
+        if !success {
+            *registry.rpc_errors.entry(method).or_default() += 1;
+        }
+    }
+
+    pub fn observe_sync(&self, duration: Duration, success: bool) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.sync_duration.observe(duration);
+        if !success {
+            registry.sync_failures += 1;
+        }
+    }
+
+    pub fn observe_reconciliation(&self, balances: usize, mismatches: usize) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.reconciled_balances += balances as u64;
+        registry.balance_mismatches += mismatches as u64;
+    }
+
+    pub fn set_indexer_height(&self, height: u64) {
+        self.registry.lock().unwrap().indexer_height = height;
+    }
+
+    pub fn set_chain_tip(&self, tip: u64) {
+        self.registry.lock().unwrap().chain_tip = tip;
+    }
+
+    pub fn render(&self) -> String {
+        let registry = self.registry.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP rosetta_requests_total Requests served per route and status.\n");
+        out.push_str("# TYPE rosetta_requests_total counter\n");
+        for ((route, status), count) in &registry.requests {
+            writeln!(
+                out,
+                "rosetta_requests_total{{route=\"{route}\",status=\"{status}\"}} {count}"
+            )
+            .unwrap();
+        }
+
+        out.push_str("# HELP rosetta_request_duration_seconds Request latency per route.\n");
+        out.push_str("# TYPE rosetta_request_duration_seconds histogram\n");
+        for (route, histogram) in &registry.request_latency {
+            let labels = format!("route=\"{route}\"");
+            histogram.render(&mut out, "rosetta_request_duration_seconds", &labels);
+        }
+
+        out.push_str(
+            "# HELP rosetta_node_rpc_duration_seconds Node rpc latency per client method.\n",
+        );
+        out.push_str("# TYPE rosetta_node_rpc_duration_seconds histogram\n");
+        for (method, histogram) in &registry.rpc_latency {
+            let labels = format!("method=\"{method}\"");
+            histogram.render(&mut out, "rosetta_node_rpc_duration_seconds", &labels);
+        }
+
+        out.push_str(
+            "# HELP rosetta_node_rpc_errors_total Failed node rpc calls per client method.\n",
+        );
+        out.push_str("# TYPE rosetta_node_rpc_errors_total counter\n");
+        for (method, count) in &registry.rpc_errors {
+            writeln!(
+                out,
+                "rosetta_node_rpc_errors_total{{method=\"{method}\"}} {count}"
+            )
+            .unwrap();
+        }
+
+        out.push_str("# HELP rosetta_indexer_height Highest indexed block.\n");
+        out.push_str("# TYPE rosetta_indexer_height gauge\n");
+        writeln!(out, "rosetta_indexer_height {}", registry.indexer_height).unwrap();
+        out.push_str("# HELP rosetta_chain_tip Current block reported by the node.\n");
+        out.push_str("# TYPE rosetta_chain_tip gauge\n");
+        writeln!(out, "rosetta_chain_tip {}", registry.chain_tip).unwrap();
+        out.push_str("# HELP rosetta_indexer_lag Blocks the indexer is behind the chain tip.\n");
+        out.push_str("# TYPE rosetta_indexer_lag gauge\n");
+        let lag = registry.chain_tip.saturating_sub(registry.indexer_height);
+        writeln!(out, "rosetta_indexer_lag {lag}").unwrap();
+
+        out.push_str("# HELP rosetta_sync_duration_seconds Duration of indexer sync iterations.\n");
+        out.push_str("# TYPE rosetta_sync_duration_seconds histogram\n");
+        registry
+            .sync_duration
+            .render(&mut out, "rosetta_sync_duration_seconds", "");
+        out.push_str("# HELP rosetta_sync_failures_total Failed indexer sync iterations.\n");
+        out.push_str("# TYPE rosetta_sync_failures_total counter\n");
+        writeln!(
+            out,
+            "rosetta_sync_failures_total {}",
+            registry.sync_failures
+        )
+        .unwrap();
+
+        out.push_str(
+            "# HELP rosetta_reconciled_balances_total Balance changes compared with the node.\n",
+        );
+        out.push_str("# TYPE rosetta_reconciled_balances_total counter\n");
+        writeln!(
+            out,
+            "rosetta_reconciled_balances_total {}",
+            registry.reconciled_balances
+        )
+        .unwrap();
+        out.push_str(
+            "# HELP rosetta_balance_mismatches_total Balance changes that don't add up to the operations.\n",
+        );
+        out.push_str("# TYPE rosetta_balance_mismatches_total counter\n");
+        writeln!(
+            out,
+            "rosetta_balance_mismatches_total {}",
+            registry.balance_mismatches
+        )
+        .unwrap();
+        out
+    }
+}
+
+/// Records the count and latency of every request by route.
+pub struct MetricsMiddleware {
+    metrics: Metrics,
+}
+
+impl MetricsMiddleware {
+    pub fn new(metrics: Metrics) -> Self {
+        Self { metrics }
+    }
+}
+
+#[async_trait::async_trait]
+impl<State: Clone + Send + Sync + 'static> Middleware<State> for MetricsMiddleware {
+    async fn handle(&self, req: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let route = req.url().path().to_string();
+        let start = Instant::now();
+        let res: Response = next.run(req).await;
+        let status = res.status() as u16;
+        // unknown paths would otherwise create a label per request
+        let route = if status == 404 {
+            "unknown".into()
+        } else {
+            route
+        };
+        self.metrics
+            .observe_request(&route, status, start.elapsed());
+        Ok(res)
+    }
+}
+
+/// Wraps a client to record the latency and errors of every node rpc.
+pub struct MeteredClient<C> {
+    client: C,
+    metrics: Metrics,
+}
+
+impl<C: BlockchainClient> MeteredClient<C> {
+    pub fn new(client: C, metrics: Metrics) -> Self {
+        Self { client, metrics }
+    }
+
+    async fn observe<R>(
+        &self,
+        method: &'static str,
+        future: impl Future<Output = Result<R>>,
+    ) -> Result<R> {
+        let start = Instant::now();
+        let result = future.await;
+        self.metrics
+            .observe_rpc(method, start.elapsed(), result.is_ok());
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl<C: BlockchainClient> BlockchainClient for MeteredClient<C> {
+    type MetadataParams = C::MetadataParams;
+    type Metadata = C::Metadata;
+    type TransactionBuilder = C::TransactionBuilder;
+
+    fn create_config(network: &str) -> Result<BlockchainConfig> {
+        C::create_config(network)
+    }
+
+    async fn new(network: &str, addr: &str) -> Result<Self> {
+        Ok(Self::new(C::new(network, addr).await?, Metrics::default()))
+    }
+
+    fn config(&self) -> &BlockchainConfig {
+        self.client.config()
+    }
+
+    fn genesis_block(&self) -> &BlockIdentifier {
+        self.client.genesis_block()
+    }
+
+    fn operation_statuses(&self) -> Vec<OperationStatus> {
+        self.client.operation_statuses()
+    }
+
+    fn operation_types(&self) -> Vec<String> {
+        self.client.operation_types()
+    }
+
+    fn call_methods(&self) -> Vec<String> {
+        self.client.call_methods()
+    }
+
+    fn balance_exemptions(&self) -> Vec<BalanceExemption> {
+        self.client.balance_exemptions()
+    }
+
+    async fn node_version(&self) -> Result<String> {
+        self.observe("node_version", self.client.node_version())
+            .await
+    }
+
+    async fn current_block(&self) -> Result<BlockIdentifier> {
+        let block = self
+            .observe("current_block", self.client.current_block())
+            .await?;
+        self.metrics.set_chain_tip(block.index);
+        Ok(block)
+    }
+
+    async fn finalized_block(&self) -> Result<BlockIdentifier> {
+        self.observe("finalized_block", self.client.finalized_block())
+            .await
+    }
+
+    async fn block_identifier(&self, block: &PartialBlockIdentifier) -> Result<BlockIdentifier> {
+        self.observe("block_identifier", self.client.block_identifier(block))
+            .await
+    }
+
+    async fn block_timestamp(&self, block: &BlockIdentifier) -> Result<i64> {
+        self.observe("block_timestamp", self.client.block_timestamp(block))
+            .await
+    }
+
+    async fn peers(&self) -> Result<Vec<Peer>> {
+        self.observe("peers", self.client.peers()).await
+    }
+
+    async fn syncing(&self) -> Result<bool> {
+        self.observe("syncing", self.client.syncing()).await
+    }
+
+    async fn balance(&self, address: &Address, block: &BlockIdentifier) -> Result<u128> {
+        self.observe("balance", self.client.balance(address, block))
+            .await
+    }
+
+    async fn coins(&self, address: &Address, block: &BlockIdentifier) -> Result<Vec<Coin>> {
+        self.observe("coins", self.client.coins(address, block))
+            .await
+    }
+
+    async fn faucet(&self, address: &Address, param: u128) -> Result<Vec<u8>> {
+        self.observe("faucet", self.client.faucet(address, param))
+            .await
+    }
+
+    async fn metadata(
+        &self,
+        public_key: &PublicKey,
+        params: &Self::MetadataParams,
+    ) -> Result<Self::Metadata> {
+        self.observe("metadata", self.client.metadata(public_key, params))
+            .await
+    }
+
+    async fn submit(&self, transaction: &[u8]) -> Result<Vec<u8>> {
+        self.observe("submit", self.client.submit(transaction))
+            .await
+    }
+
+    async fn block(&self, block: &PartialBlockIdentifier) -> Result<Block> {
+        self.observe("block", self.client.block(block)).await
+    }
+
+    async fn block_transaction(
+        &self,
+        block: &BlockIdentifier,
+        tx: &TransactionIdentifier,
+    ) -> Result<Transaction> {
+        self.observe(
+            "block_transaction",
+            self.client.block_transaction(block, tx),
+        )
+        .await
+    }
+
+    async fn mempool(&self) -> Result<Vec<TransactionIdentifier>> {
+        self.observe("mempool", self.client.mempool()).await
+    }
+
+    async fn mempool_transaction(&self, tx: &TransactionIdentifier) -> Result<Transaction> {
+        self.observe("mempool_transaction", self.client.mempool_transaction(tx))
+            .await
+    }
+
+    async fn call(&self, req: &CallRequest) -> Result<Value> {
+        self.observe("call", self.client.call(req)).await
+    }
+
+    async fn listen(&self) -> Result<Option<BlockStream>> {
+        self.observe("listen", self.client.listen()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::tests::MockClient;
+    use crate::indexer::Indexer;
+    use crate::storage::MemoryStorage;
+
+    #[tokio::test]
+    async fn test_render() -> Result<()> {
+        let metrics = Metrics::default();
+        let client = MeteredClient::new(MockClient::new(), metrics.clone());
+        client.current_block().await?;
+        client.call(&CallRequest::default()).await.unwrap_err();
+        metrics.observe_request("/network/status", 200, Duration::from_millis(20));
+        metrics.set_indexer_height(0);
+
+        let out = metrics.render();
+        assert!(out.contains("rosetta_requests_total{route=\"/network/status\",status=\"200\"} 1"));
+        assert!(out.contains(
+            "rosetta_request_duration_seconds_bucket{route=\"/network/status\",le=\"0.025\"} 1"
+        ));
+        assert!(out.contains("rosetta_node_rpc_duration_seconds_count{method=\"current_block\"} 1"));
+        assert!(out.contains("rosetta_node_rpc_errors_total{method=\"call\"} 1"));
+        assert!(out.contains("rosetta_sync_duration_seconds_bucket{le=\"+Inf\"} 0"));
+        assert!(out.contains("rosetta_indexer_lag 0"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_chain_tip_finalized_only() -> Result<()> {
+        let metrics = Metrics::default();
+        let client = MockClient::new();
+        client.extend(0, 0, 3, &["alice"]);
+        client.finalize(1);
+        let indexer = Indexer::new(
+            Arc::new(MemoryStorage::default()),
+            MeteredClient::new(client, metrics.clone()),
+        )
+        .with_finalized_only(true);
+        indexer.sync().await?;
+        metrics.set_indexer_height(indexer.height()?);
+
+        let out = metrics.render();
+        assert!(out.contains("rosetta_chain_tip 3"));
+        assert!(out.contains("rosetta_indexer_lag 2"));
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+//! Snapshots of the index, so that new connectors can be bootstrapped without syncing
+//! from genesis.
+//!
+//! A snapshot is a deflate compressed stream starting with a header that identifies the
+//! network and the schema version of the index, followed by the entries of every table and
+//! an end marker.
+use crate::indexer::SCHEMA_VERSION;
+use crate::storage::{Batch, Storage, Table};
+use crate::BlockchainConfig;
+use anyhow::{ensure, Context, Result};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"rosetta\x01";
+
+/// Marks the end of the entries, so that truncated snapshots are detected.
+const END: u8 = u8::MAX;
+
+/// Number of entries written to the storage per batch while importing.
+#[cfg(not(test))]
+const IMPORT_BATCH_SIZE: usize = 10_000;
+/// Small batches, so that the tests fail imports after the first batches are written.
+#[cfg(test)]
+const IMPORT_BATCH_SIZE: usize = 2;
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_bytes(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let mut bytes = vec![0; u32::from_be_bytes(len) as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn network(config: &BlockchainConfig) -> String {
+    format!("{}/{}", config.blockchain, config.network)
+}
+
+/// Writes all tables of the storage to `path`, returns the number of entries written.
+pub fn export(storage: &dyn Storage, config: &BlockchainConfig, path: &Path) -> Result<u64> {
+    let file = File::create(path)?;
+    let mut writer = DeflateEncoder::new(BufWriter::new(file), Compression::default());
+    writer.write_all(MAGIC)?;
+    write_bytes(&mut writer, network(config).as_bytes())?;
+    writer.write_all(&SCHEMA_VERSION.to_be_bytes())?;
+    let mut entries = 0;
+    for table in Table::ALL {
+        for entry in storage.range(table, &[], None, false) {
+            let (key, value) = entry?;
+            writer.write_all(&[table as u8])?;
+            write_bytes(&mut writer, &key)?;
+            write_bytes(&mut writer, &value)?;
+            entries += 1;
+        }
+    }
+    writer.write_all(&[END])?;
+    writer.finish()?.flush()?;
+    Ok(entries)
+}
+
+/// Loads a snapshot written by [`export`] into an empty storage, returns the number of
+/// entries imported. The storage is cleared again if the import fails.
+pub fn import(storage: &dyn Storage, config: &BlockchainConfig, path: &Path) -> Result<u64> {
+    for table in Table::ALL {
+        ensure!(
+            storage.range(table, &[], None, false).next().is_none(),
+            "snapshots can only be imported into an empty index"
+        );
+    }
+    import_entries(storage, config, path).inspect_err(|_| {
+        for table in Table::ALL {
+            if let Err(err) = storage.clear(table) {
+                log::error!(
+                    "failed to clear {} after a failed import: {}",
+                    table.name(),
+                    err
+                );
+            }
+        }
+    })
+}
+
+fn import_entries(storage: &dyn Storage, config: &BlockchainConfig, path: &Path) -> Result<u64> {
+    let file = File::open(path)?;
+    let mut reader = DeflateDecoder::new(BufReader::new(file));
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    ensure!(
+        &magic == MAGIC,
+        "{} is not an index snapshot",
+        path.display()
+    );
+    let snapshot_network = String::from_utf8(read_bytes(&mut reader)?)?;
+    ensure!(
+        snapshot_network == network(config),
+        "snapshot of {} can't be imported into {}",
+        snapshot_network,
+        network(config)
+    );
+    let mut version = [0; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_be_bytes(version);
+    ensure!(
+        version <= SCHEMA_VERSION,
+        "snapshot schema version {} is newer than the supported version {}",
+        version,
+        SCHEMA_VERSION
+    );
+    let mut entries = 0;
+    let mut batch = Batch::default();
+    let mut pending = 0;
+    loop {
+        let mut table = [0];
+        reader
+            .read_exact(&mut table)
+            .context("snapshot is truncated")?;
+        if table[0] == END {
+            break;
+        }
+        let table = *Table::ALL
+            .get(table[0] as usize)
+            .context("snapshot contains an unknown table")?;
+        let key = read_bytes(&mut reader)?;
+        let value = read_bytes(&mut reader)?;
+        batch.insert(table, &key, &value);
+        entries += 1;
+        pending += 1;
+        if pending == IMPORT_BATCH_SIZE {
+            storage.commit(std::mem::take(&mut batch))?;
+            pending = 0;
+        }
+    }
+    storage.commit(batch)?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::tests::MockClient;
+    use crate::indexer::Indexer;
+    use crate::storage::{Entry, MemoryStorage};
+    use crate::BlockchainClient;
+    use std::sync::Arc;
+
+    fn entries(storage: &dyn Storage) -> Result<Vec<(Table, Entry)>> {
+        let mut entries = vec![];
+        for table in Table::ALL {
+            for entry in storage.range(table, &[], None, false) {
+                entries.push((table, entry?));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Modifies the decompressed contents of a snapshot.
+    fn rewrite(path: &Path, f: impl FnOnce(&mut Vec<u8>)) -> Result<()> {
+        let mut bytes = vec![];
+        DeflateDecoder::new(File::open(path)?).read_to_end(&mut bytes)?;
+        f(&mut bytes);
+        let mut writer = DeflateEncoder::new(File::create(path)?, Compression::default());
+        writer.write_all(&bytes)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot() -> Result<()> {
+        let client = MockClient::new();
+        let storage = Arc::new(MemoryStorage::default());
+        let indexer = Indexer::new(storage.clone(), client.clone());
+        indexer.migrate()?;
+        client.extend(0, 0, 3, &["alice", "bob"]);
+        indexer.sync().await?;
+        let config = client.config().clone();
+        let path = std::env::temp_dir().join(format!("snapshot-{}", std::process::id()));
+
+        let exported = export(&*storage, &config, &path)?;
+        assert_eq!(exported, entries(&*storage)?.len() as u64);
+
+        let imported = MemoryStorage::default();
+        assert_eq!(import(&imported, &config, &path)?, exported);
+        assert_eq!(entries(&imported)?, entries(&*storage)?);
+        assert!(import(&imported, &config, &path).is_err());
+
+        let mut other = config.clone();
+        other.network = "other";
+        assert!(import(&MemoryStorage::default(), &other, &path).is_err());
+
+        // snapshots of newer schema versions are rejected
+        let offset = MAGIC.len() + 4 + network(&config).len();
+        rewrite(&path, |bytes| {
+            bytes[offset..offset + 4].copy_from_slice(&(SCHEMA_VERSION + 1).to_be_bytes())
+        })?;
+        assert!(import(&MemoryStorage::default(), &config, &path).is_err());
+
+        // truncated snapshots leave the storage empty
+        rewrite(&path, |bytes| {
+            bytes[offset..offset + 4].copy_from_slice(&SCHEMA_VERSION.to_be_bytes());
+            bytes.pop();
+        })?;
+        let truncated = MemoryStorage::default();
+        assert!(import(&truncated, &config, &path).is_err());
+        assert!(entries(&truncated)?.is_empty());
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,474 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use sled::transaction::{ConflictableTransactionError, Transactional};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Tables of the indexer, each one is stored in a separate keyspace.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Table {
+    Transactions,
+    Accounts,
+    Blocks,
+    Events,
+    BlockStore,
+    BlockHashes,
+    Balances,
+    Filters,
+    Meta,
+    Counts,
+}
+
+impl Table {
+    pub const ALL: [Self; 10] = [
+        Self::Transactions,
+        Self::Accounts,
+        Self::Blocks,
+        Self::Events,
+        Self::BlockStore,
+        Self::BlockHashes,
+        Self::Balances,
+        Self::Filters,
+        Self::Meta,
+        Self::Counts,
+    ];
+
+    /// Name of the keyspace, existing sled databases use the same names.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Transactions => "transaction_table",
+            Self::Accounts => "account_table",
+            Self::Blocks => "block_table",
+            Self::Events => "event_table",
+            Self::BlockStore => "block_store",
+            Self::BlockHashes => "block_hash_table",
+            Self::Balances => "balance_table",
+            Self::Filters => "filter_table",
+            Self::Meta => "meta_table",
+            Self::Counts => "count_table",
+        }
+    }
+}
+
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+pub type Iter<'a> = Box<dyn Iterator<Item = Result<Entry>> + Send + 'a>;
+
+/// Ordered key-value store holding the indexer tables.
+pub trait Storage: Send + Sync {
+    fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    /// Iterates over the entries with a key in `start..end`, in reverse order when `rev` is
+    /// set. An `end` of `None` is unbounded.
+    fn range(&self, table: Table, start: &[u8], end: Option<&[u8]>, rev: bool) -> Iter<'_>;
+
+    /// Applies all writes of the batch atomically.
+    fn commit(&self, batch: Batch) -> Result<()>;
+
+    /// Removes all entries of the table.
+    fn clear(&self, table: Table) -> Result<()>;
+}
+
+/// Writes to the indexer tables that are committed atomically.
+#[derive(Default)]
+pub struct Batch {
+    writes: Vec<(Table, Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl Batch {
+    pub fn insert(&mut self, table: Table, key: &[u8], value: &[u8]) {
+        self.writes
+            .push((table, key.to_vec(), Some(value.to_vec())));
+    }
+
+    pub fn remove(&mut self, table: Table, key: &[u8]) {
+        self.writes.push((table, key.to_vec(), None));
+    }
+}
+
+/// A table of the storage.
+#[derive(Clone)]
+pub struct Tree {
+    storage: Arc<dyn Storage>,
+    table: Table,
+}
+
+impl std::fmt::Debug for Tree {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("Tree").field(&self.table).finish()
+    }
+}
+
+impl Tree {
+    pub fn new(storage: Arc<dyn Storage>, table: Table) -> Self {
+        Self { storage, table }
+    }
+
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+        self.storage.get(self.table, key.as_ref())
+    }
+
+    pub fn range(&self, start: &[u8], end: Option<&[u8]>) -> Iter<'_> {
+        self.iter(start, end, false)
+    }
+
+    pub fn range_rev(&self, start: &[u8], end: Option<&[u8]>) -> Iter<'_> {
+        self.iter(start, end, true)
+    }
+
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Iter<'_> {
+        self.range(prefix, prefix_end(prefix).as_deref())
+    }
+
+    pub fn last(&self) -> Result<Option<Entry>> {
+        self.range_rev(&[], None).next().transpose()
+    }
+
+    pub fn insert(&self, batch: &mut Batch, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) {
+        batch.insert(self.table, key.as_ref(), value.as_ref());
+    }
+
+    pub fn remove(&self, batch: &mut Batch, key: impl AsRef<[u8]>) {
+        batch.remove(self.table, key.as_ref());
+    }
+
+    fn iter(&self, start: &[u8], end: Option<&[u8]>, rev: bool) -> Iter<'_> {
+        if end.map(|end| end <= start).unwrap_or_default() {
+            return Box::new(std::iter::empty());
+        }
+        self.storage.range(self.table, start, end, rev)
+    }
+}
+
+/// Returns the first key after all keys starting with `prefix`.
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+/// Storage backend selected with `--backend`.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum Backend {
+    #[default]
+    Sled,
+    Rocksdb,
+}
+
+impl Backend {
+    pub fn open(self, path: &Path) -> Result<Arc<dyn Storage>> {
+        Ok(match self {
+            Self::Sled => Arc::new(SledStorage::open(path)?),
+            #[cfg(feature = "rocksdb")]
+            Self::Rocksdb => Arc::new(RocksStorage::open(path)?),
+            #[cfg(not(feature = "rocksdb"))]
+            Self::Rocksdb => anyhow::bail!("rosetta-server was built without the rocksdb feature"),
+        })
+    }
+}
+
+pub struct SledStorage {
+    trees: Vec<sled::Tree>,
+}
+
+impl SledStorage {
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::from_db(&sled::open(path)?)
+    }
+
+    pub fn from_db(db: &sled::Db) -> Result<Self> {
+        let trees = Table::ALL
+            .iter()
+            .map(|table| db.open_tree(table.name()))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { trees })
+    }
+
+    fn tree(&self, table: Table) -> &sled::Tree {
+        &self.trees[table as usize]
+    }
+}
+
+impl Storage for SledStorage {
+    fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.tree(table).get(key)?.map(|value| value.to_vec()))
+    }
+
+    fn range(&self, table: Table, start: &[u8], end: Option<&[u8]>, rev: bool) -> Iter<'_> {
+        let tree = self.tree(table);
+        let iter = match end {
+            Some(end) => tree.range(start..end),
+            None => tree.range(start..),
+        }
+        .map(|entry| {
+            let (key, value) = entry?;
+            Ok((key.to_vec(), value.to_vec()))
+        });
+        if rev {
+            Box::new(iter.rev())
+        } else {
+            Box::new(iter)
+        }
+    }
+
+    fn commit(&self, batch: Batch) -> Result<()> {
+        let mut batches = vec![sled::Batch::default(); self.trees.len()];
+        for (table, key, value) in batch.writes {
+            match value {
+                Some(value) => batches[table as usize].insert(key, value),
+                None => batches[table as usize].remove(key),
+            }
+        }
+        self.trees[..].transaction(|trees| {
+            for (tree, batch) in trees.iter().zip(&batches) {
+                tree.apply_batch(batch)?;
+            }
+            Ok::<_, ConflictableTransactionError>(())
+        })?;
+        Ok(())
+    }
+
+    fn clear(&self, table: Table) -> Result<()> {
+        self.tree(table).clear()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub use memory::MemoryStorage;
+
+/// Storage for tests, the production backends are persistent.
+#[cfg(test)]
+mod memory {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+    use std::sync::Mutex;
+
+    type MemoryTable = BTreeMap<Vec<u8>, Vec<u8>>;
+
+    /// Keeps the tables in memory, nothing is persisted.
+    #[derive(Default)]
+    pub struct MemoryStorage {
+        tables: Mutex<BTreeMap<Table, MemoryTable>>,
+    }
+
+    /// Iterates over a range of a memory table, the table is only locked while an entry
+    /// is looked up.
+    struct MemoryIter<'a> {
+        storage: &'a MemoryStorage,
+        table: Table,
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+        rev: bool,
+    }
+
+    impl<'a> Iterator for MemoryIter<'a> {
+        type Item = Result<Entry>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let tables = self.storage.tables.lock().unwrap();
+            let mut range = tables
+                .get(&self.table)?
+                .range((self.start.clone(), self.end.clone()));
+            let (key, value) = if self.rev {
+                range.next_back()?
+            } else {
+                range.next()?
+            };
+            if self.rev {
+                self.end = Bound::Excluded(key.clone());
+            } else {
+                self.start = Bound::Excluded(key.clone());
+            }
+            Some(Ok((key.clone(), value.clone())))
+        }
+    }
+
+    impl Storage for MemoryStorage {
+        fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            let tables = self.tables.lock().unwrap();
+            Ok(tables.get(&table).and_then(|table| table.get(key)).cloned())
+        }
+
+        fn range(&self, table: Table, start: &[u8], end: Option<&[u8]>, rev: bool) -> Iter<'_> {
+            Box::new(MemoryIter {
+                storage: self,
+                table,
+                start: Bound::Included(start.to_vec()),
+                end: end.map_or(Bound::Unbounded, |end| Bound::Excluded(end.to_vec())),
+                rev,
+            })
+        }
+
+        fn commit(&self, batch: Batch) -> Result<()> {
+            let mut tables = self.tables.lock().unwrap();
+            for (table, key, value) in batch.writes {
+                let table = tables.entry(table).or_default();
+                match value {
+                    Some(value) => table.insert(key, value),
+                    None => table.remove(&key),
+                };
+            }
+            Ok(())
+        }
+
+        fn clear(&self, table: Table) -> Result<()> {
+            self.tables.lock().unwrap().remove(&table);
+            Ok(())
+        }
+    }
+}
+
+/// Stores each table in a column family, for archives that outgrow sled.
+#[cfg(feature = "rocksdb")]
+pub struct RocksStorage {
+    db: rocksdb::DB,
+}
+
+#[cfg(feature = "rocksdb")]
+impl RocksStorage {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut options = rocksdb::Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        let names = Table::ALL.iter().map(|table| table.name());
+        let db = rocksdb::DB::open_cf(&options, path, names)?;
+        Ok(Self { db })
+    }
+
+    fn cf(&self, table: Table) -> Result<&rocksdb::ColumnFamily> {
+        self.db
+            .cf_handle(table.name())
+            .ok_or_else(|| anyhow::anyhow!("missing column family {}", table.name()))
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+impl Storage for RocksStorage {
+    fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.db.get_cf(self.cf(table)?, key)?)
+    }
+
+    fn range(&self, table: Table, start: &[u8], end: Option<&[u8]>, rev: bool) -> Iter<'_> {
+        let cf = match self.cf(table) {
+            Ok(cf) => cf,
+            Err(err) => return Box::new(std::iter::once(Err(err))),
+        };
+        let mut options = rocksdb::ReadOptions::default();
+        options.set_iterate_lower_bound(start);
+        if let Some(end) = end {
+            options.set_iterate_upper_bound(end);
+        }
+        let mode = if rev {
+            rocksdb::IteratorMode::End
+        } else {
+            rocksdb::IteratorMode::Start
+        };
+        Box::new(self.db.iterator_cf_opt(cf, options, mode).map(|entry| {
+            let (key, value) = entry?;
+            Ok((key.into_vec(), value.into_vec()))
+        }))
+    }
+
+    fn commit(&self, batch: Batch) -> Result<()> {
+        let mut writes = rocksdb::WriteBatch::default();
+        for (table, key, value) in batch.writes {
+            let cf = self.cf(table)?;
+            match value {
+                Some(value) => writes.put_cf(cf, key, value),
+                None => writes.delete_cf(cf, key),
+            }
+        }
+        self.db.write(writes)?;
+        Ok(())
+    }
+
+    fn clear(&self, table: Table) -> Result<()> {
+        let mut writes = rocksdb::WriteBatch::default();
+        for key in self
+            .db
+            .iterator_cf(self.cf(table)?, rocksdb::IteratorMode::Start)
+        {
+            writes.delete_cf(self.cf(table)?, key?.0);
+        }
+        self.db.write(writes)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(iter: Iter) -> Result<Vec<Vec<u8>>> {
+        iter.map(|entry| Ok(entry?.0)).collect()
+    }
+
+    fn check(storage: Arc<dyn Storage>) -> Result<()> {
+        let tree = Tree::new(storage.clone(), Table::Accounts);
+        let other = Tree::new(storage.clone(), Table::Filters);
+        let mut batch = Batch::default();
+        for key in [&b"a"[..], b"ab", b"ab\xff", b"b", b"\xff"] {
+            tree.insert(&mut batch, key, key);
+        }
+        other.insert(&mut batch, b"ab", b"other");
+        storage.commit(batch)?;
+
+        assert_eq!(tree.get(b"ab")?, Some(b"ab".to_vec()));
+        assert_eq!(other.get(b"ab")?, Some(b"other".to_vec()));
+        assert_eq!(other.get(b"a")?, None);
+        assert_eq!(
+            keys(tree.scan_prefix(b"ab"))?,
+            vec![b"ab".to_vec(), b"ab\xff".to_vec()]
+        );
+        assert_eq!(keys(tree.range(b"a", Some(b"b")))?.len(), 3);
+        assert_eq!(
+            keys(tree.range_rev(b"ab", None))?,
+            vec![
+                b"\xff".to_vec(),
+                b"b".to_vec(),
+                b"ab\xff".to_vec(),
+                b"ab".to_vec()
+            ]
+        );
+        assert!(keys(tree.range(b"b", Some(b"a")))?.is_empty());
+        assert_eq!(tree.last()?.map(|(key, _)| key), Some(b"\xff".to_vec()));
+
+        let mut batch = Batch::default();
+        tree.remove(&mut batch, b"\xff");
+        other.remove(&mut batch, b"ab");
+        storage.commit(batch)?;
+        assert_eq!(tree.last()?.map(|(key, _)| key), Some(b"b".to_vec()));
+        assert!(other.last()?.is_none());
+
+        storage.clear(Table::Accounts)?;
+        assert!(tree.last()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sled() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        check(Arc::new(SledStorage::from_db(&db)?))
+    }
+
+    #[test]
+    fn test_memory() -> Result<()> {
+        check(Arc::new(MemoryStorage::default()))
+    }
+
+    #[cfg(feature = "rocksdb")]
+    #[test]
+    fn test_rocksdb() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rocksdb-{}", std::process::id()));
+        check(Arc::new(RocksStorage::open(&path)?))?;
+        rocksdb::DB::destroy(&rocksdb::Options::default(), &path)?;
+        Ok(())
+    }
+}
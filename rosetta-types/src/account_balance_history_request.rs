@@ -0,0 +1,43 @@
+use crate::{AccountIdentifier, Currency, NetworkIdentifier};
+
+/// AccountBalanceHistoryRequest : AccountBalanceHistoryRequest is utilized to make a request on the /account/balance/history endpoint.
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct AccountBalanceHistoryRequest {
+    #[serde(rename = "network_identifier")]
+    pub network_identifier: NetworkIdentifier,
+    #[serde(rename = "account_identifier")]
+    pub account_identifier: AccountIdentifier,
+    /// If the currencies field is populated, only balance changes of the specified currencies are returned.
+    #[serde(rename = "currencies", skip_serializing_if = "Option::is_none")]
+    pub currencies: Option<Vec<Currency>>,
+    /// min_block is the smallest block index to return balance changes for.
+    #[serde(rename = "min_block", skip_serializing_if = "Option::is_none")]
+    pub min_block: Option<i64>,
+    /// max_block is the largest block index to return balance changes for. If this field is not populated, the indexed height is considered the max_block.
+    #[serde(rename = "max_block", skip_serializing_if = "Option::is_none")]
+    pub max_block: Option<i64>,
+    /// offset is the offset into the query result to start returning balance changes.
+    #[serde(rename = "offset", skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+    /// limit is the maximum number of balance changes to return in one call.
+    #[serde(rename = "limit", skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+}
+
+impl AccountBalanceHistoryRequest {
+    /// AccountBalanceHistoryRequest is utilized to make a request on the /account/balance/history endpoint.
+    pub fn new(
+        network_identifier: NetworkIdentifier,
+        account_identifier: AccountIdentifier,
+    ) -> AccountBalanceHistoryRequest {
+        AccountBalanceHistoryRequest {
+            network_identifier,
+            account_identifier,
+            currencies: None,
+            min_block: None,
+            max_block: None,
+            offset: None,
+            limit: None,
+        }
+    }
+}
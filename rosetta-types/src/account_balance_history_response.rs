@@ -0,0 +1,21 @@
+use crate::BalanceChange;
+
+/// AccountBalanceHistoryResponse : AccountBalanceHistoryResponse is returned on the /account/balance/history endpoint and contains the balance changes of an account ordered by currency and block.
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct AccountBalanceHistoryResponse {
+    #[serde(rename = "balances")]
+    pub balances: Vec<BalanceChange>,
+    /// next_offset is the next offset to use when paginating through balance changes. If this field is not populated, there are no more balance changes to query.
+    #[serde(rename = "next_offset", skip_serializing_if = "Option::is_none")]
+    pub next_offset: Option<i64>,
+}
+
+impl AccountBalanceHistoryResponse {
+    /// AccountBalanceHistoryResponse is returned on the /account/balance/history endpoint and contains the balance changes of an account ordered by currency and block.
+    pub fn new(balances: Vec<BalanceChange>) -> AccountBalanceHistoryResponse {
+        AccountBalanceHistoryResponse {
+            balances,
+            next_offset: None,
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use crate::{Amount, BlockIdentifier};
+
+/// BalanceChange : BalanceChange is the net change of an account's balance in a block and the balance after the block.
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct BalanceChange {
+    #[serde(rename = "block_identifier")]
+    pub block_identifier: BlockIdentifier,
+    /// Sum of the amounts of all successful operations on the account in the block.
+    #[serde(rename = "delta")]
+    pub delta: Amount,
+    /// Balance of the account after the block, computed from the operations since the first indexed block.
+    #[serde(rename = "balance")]
+    pub balance: Amount,
+}
+
+impl BalanceChange {
+    /// BalanceChange is the net change of an account's balance in a block and the balance after the block.
+    pub fn new(block_identifier: BlockIdentifier, delta: Amount, balance: Amount) -> BalanceChange {
+        BalanceChange {
+            block_identifier,
+            delta,
+            balance,
+        }
+    }
+}
@@ -1,6 +1,10 @@
 #[macro_use]
 extern crate serde;
 
+pub mod account_balance_history_request;
+pub use self::account_balance_history_request::AccountBalanceHistoryRequest;
+pub mod account_balance_history_response;
+pub use self::account_balance_history_response::AccountBalanceHistoryResponse;
 pub mod account_balance_request;
 pub use self::account_balance_request::AccountBalanceRequest;
 pub mod account_balance_response;
@@ -17,6 +21,8 @@ pub mod allow;
 pub use self::allow::Allow;
 pub mod amount;
 pub use self::amount::Amount;
+pub mod balance_change;
+pub use self::balance_change::BalanceChange;
 pub mod balance_exemption;
 pub use self::balance_exemption::BalanceExemption;
 pub mod block;
@@ -25,6 +25,9 @@ pub struct SearchTransactionsRequest {
     /// limit is the maximum number of transactions to return in one call. The implementation may return <= limit transactions.
     #[serde(rename = "limit", skip_serializing_if = "Option::is_none")]
     pub limit: Option<i64>,
+    /// cursor is the next_cursor returned by a previous search. Unlike offset it stays valid while new blocks are indexed, results continue after the last transaction of the previous page.
+    #[serde(rename = "cursor", skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
     #[serde(
         rename = "transaction_identifier",
         skip_serializing_if = "Option::is_none"
@@ -59,6 +62,7 @@ impl SearchTransactionsRequest {
             max_block: None,
             offset: None,
             limit: None,
+            cursor: None,
             transaction_identifier: None,
             account_identifier: None,
             coin_identifier: None,
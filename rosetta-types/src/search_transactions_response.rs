@@ -21,6 +21,9 @@ pub struct SearchTransactionsResponse {
     /// next_offset is the next offset to use when paginating through transaction results. If this field is not populated, there are no more transactions to query.
     #[serde(rename = "next_offset", skip_serializing_if = "Option::is_none")]
     pub next_offset: Option<i64>,
+    /// next_cursor is an opaque token to pass as cursor to fetch the next page of results. If this field is not populated, there are no more transactions to query.
+    #[serde(rename = "next_cursor", skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 impl SearchTransactionsResponse {
@@ -33,6 +36,7 @@ impl SearchTransactionsResponse {
             transactions,
             total_count,
             next_offset: None,
+            next_cursor: None,
         }
     }
 }